@@ -92,7 +92,7 @@ fn main() {
         end_x: 10.0,
         props: adsr,
     };
-    // let osc = happy_synth::osc::square::SquareOscillator;
+    // let osc = happy_synth::osc::square::SquareOscillator::default();
     let osc = happy_synth::osc::harmonic::HarmonicOscillator::new(&[
         1.0, 0.5, 0.33, 0.25, 0.18, 0.14, 0.125, 0.11, 0.1,
     ]);
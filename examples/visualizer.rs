@@ -5,7 +5,7 @@ fn main() {
         sample_rate: 4000.0,
         ..Default::default()
     };
-    let osc = SineOscillator;
+    let osc = SineOscillator::default();
     let adsr = AdsrEnvelope {
         attack: 0.01,
         decay: 0.01,
@@ -1,6 +1,4 @@
-use crate::note::NoteState;
-
-use super::Envelope;
+use super::{Envelope, NoteContext};
 
 /// An ADSR envelope configuration. All times are in seconds.
 ///
@@ -17,6 +15,7 @@ use super::Envelope;
 ///   |    +--+ Decay
 /// t=0----+ Attack
 /// ```
+#[derive(Debug, Clone)]
 pub struct AdsrEnvelope {
     /// The time it takes for the envelope to reach its maximum amplitude.
     pub attack: f32,
@@ -28,14 +27,45 @@ pub struct AdsrEnvelope {
     pub release: f32,
 }
 
+/// The per-note state of [`AdsrEnvelope`] and [`ExponentialAdsrEnvelope`].
+#[derive(Debug, Clone, Default)]
+pub struct AdsrEnvelopeState {
+    /// The level the attack starts from, which is only non-zero after a retrigger.
+    start_level: f32,
+    /// The last sampled level.
+    level: f32,
+    /// The level at which the note was released, once it is.
+    release_level: Option<f32>,
+}
+
+impl AdsrEnvelopeState {
+    /// The level the release starts from, which is the last level sampled while holding.
+    fn release_level(&mut self) -> f32 {
+        *self.release_level.get_or_insert(self.level)
+    }
+
+    fn retrigger(&mut self) {
+        self.start_level = self.level;
+        self.release_level = None;
+    }
+}
+
 impl Envelope for AdsrEnvelope {
-    fn sample(&self, state: NoteState) -> f32 {
-        match state {
-            NoteState::Holding(time) => {
+    type State = AdsrEnvelopeState;
+
+    fn create_state(&self) -> Self::State {
+        AdsrEnvelopeState::default()
+    }
+
+    fn sample(&self, state: &mut Self::State, note: NoteContext) -> f32 {
+        let level = match note.time_released {
+            None => {
+                let time = note.time_held;
+                let start = state.start_level;
                 if time < 0.0 {
-                    0.0
+                    start
                 } else if time < self.attack {
-                    1.0 - time / self.attack
+                    start + (1.0 - start) * (time / self.attack)
                 } else if time < self.attack + self.decay {
                     let decay_time = time - self.attack;
                     1.0 + (self.sustain - 1.0) * (decay_time / self.decay)
@@ -43,22 +73,29 @@ impl Envelope for AdsrEnvelope {
                     self.sustain
                 }
             }
-            NoteState::Released(time) => {
+            Some(time) => {
+                let start = state.release_level();
                 if time < self.release {
-                    1.0 - time / self.release * self.sustain
+                    start * (1.0 - time / self.release)
                 } else {
                     0.0
                 }
             }
-        }
+        };
+        state.level = level;
+        level
     }
 
-    fn note_ended(&self, state: NoteState) -> bool {
-        match state {
-            NoteState::Holding(_) => false,
-            NoteState::Released(time) => time >= self.release,
+    fn note_ended(&self, _state: &Self::State, note: NoteContext) -> bool {
+        match note.time_released {
+            None => false,
+            Some(time) => time >= self.release,
         }
     }
+
+    fn retrigger(&self, state: &mut Self::State) {
+        state.retrigger();
+    }
 }
 
 impl AdsrEnvelope {
@@ -88,6 +125,7 @@ impl Default for AdsrEnvelope {
 }
 
 /// An ADSR envelope that uses an exponential function for the phase transitions.
+#[derive(Debug, Clone)]
 pub struct ExponentialAdsrEnvelope {
     /// The ending x value of the exponential function.
     pub end_x: f32,
@@ -108,14 +146,22 @@ fn sample_exp(y_start: f32, y_end: f32, t_end: f32, t: f32) -> f32 {
 }
 
 impl Envelope for ExponentialAdsrEnvelope {
-    fn sample(&self, state: NoteState) -> f32 {
-        match state {
-            NoteState::Holding(time) => {
+    type State = AdsrEnvelopeState;
+
+    fn create_state(&self) -> Self::State {
+        AdsrEnvelopeState::default()
+    }
+
+    fn sample(&self, state: &mut Self::State, note: NoteContext) -> f32 {
+        let level = match note.time_released {
+            None => {
+                let time = note.time_held;
+                let start = state.start_level;
                 if time < 0.0 {
-                    0.0
+                    start
                 } else if time < self.props.attack {
                     let t = time / self.props.attack;
-                    sample_exp(0.0, 1.0, self.end_x, t)
+                    sample_exp(start, 1.0, self.end_x, t)
                 } else if time < self.props.attack + self.props.decay {
                     let t = (time - self.props.attack) / self.props.decay;
                     sample_exp(1.0, self.props.sustain, self.end_x, t)
@@ -123,19 +169,26 @@ impl Envelope for ExponentialAdsrEnvelope {
                     self.props.sustain
                 }
             }
-            NoteState::Released(time) => {
+            Some(time) => {
+                let start = state.release_level();
                 if time < self.props.release {
                     let t = time / self.props.release;
-                    sample_exp(self.props.sustain, 0.0, self.end_x, t)
+                    sample_exp(start, 0.0, self.end_x, t)
                 } else {
                     0.0
                 }
             }
-        }
+        };
+        state.level = level;
+        level
     }
 
-    fn note_ended(&self, state: NoteState) -> bool {
-        self.props.note_ended(state)
+    fn note_ended(&self, state: &Self::State, note: NoteContext) -> bool {
+        self.props.note_ended(state, note)
+    }
+
+    fn retrigger(&self, state: &mut Self::State) {
+        state.retrigger();
     }
 }
 
@@ -165,3 +218,51 @@ fn test_sample_exp() {
     assert_eq!(sample_exp(0.0, 1.0, 2.0, 0.0), 0.0);
     assert_eq!(sample_exp(0.0, 1.0, 2.0, 1.0), 1.0);
 }
+
+#[test]
+fn test_release_continues_from_current_level() {
+    let env = AdsrEnvelope::new(1.0, 1.0, 0.5, 1.0);
+    let note = NoteContext::new(440.0, 1.0, 44100.0);
+
+    // Released while sustaining: the release starts at the sustain level.
+    let mut state = env.create_state();
+    env.sample(&mut state, note.with_time_held(3.0));
+    let released = note.with_time_held(3.0);
+    assert_eq!(
+        env.sample(&mut state, released.with_time_released(0.0)),
+        0.5
+    );
+    assert_eq!(
+        env.sample(&mut state, released.with_time_released(0.5)),
+        0.25
+    );
+
+    // Released halfway through the attack: the release starts at half the amplitude.
+    let mut state = env.create_state();
+    env.sample(&mut state, note.with_time_held(0.5));
+    let released = note.with_time_held(0.5);
+    assert_eq!(
+        env.sample(&mut state, released.with_time_released(0.0)),
+        0.5
+    );
+
+    // Retriggered during the release: the attack starts from the current level.
+    env.sample(&mut state, released.with_time_released(0.5));
+    env.retrigger(&mut state);
+    assert_eq!(env.sample(&mut state, note), 0.25);
+    assert_eq!(env.sample(&mut state, note.with_time_held(1.0)), 1.0);
+}
+
+#[test]
+fn test_attack_rises_to_full_level() {
+    let env = AdsrEnvelope::new(1.0, 1.0, 0.5, 1.0);
+    let note = NoteContext::new(440.0, 1.0, 44100.0);
+    let mut state = env.create_state();
+    let levels =
+        [0.0, 0.25, 0.5, 1.0, 1.5].map(|time| env.sample(&mut state, note.with_time_held(time)));
+    assert_eq!(levels, [0.0, 0.25, 0.5, 1.0, 0.75]);
+
+    // A retriggered attack rises from the current level instead.
+    env.retrigger(&mut state);
+    assert_eq!(env.sample(&mut state, note.with_time_held(0.5)), 0.875);
+}
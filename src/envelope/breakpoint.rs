@@ -0,0 +1,246 @@
+//! An envelope made of an arbitrary list of segments.
+
+use super::{Envelope, NoteContext};
+
+/// A segment of a [`BreakpointEnvelope`], moving from the level the previous segment ended at
+/// to `level`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Segment {
+    /// The duration of the segment, in seconds.
+    pub time: f32,
+    /// The level at the end of the segment.
+    pub level: f32,
+    /// The shape of the segment. 0 is linear; positive values start slowly and end quickly,
+    /// negative values start quickly and end slowly.
+    pub curve: f32,
+}
+
+impl Segment {
+    pub fn new(time: f32, level: f32, curve: f32) -> Self {
+        Self { time, level, curve }
+    }
+
+    pub fn linear(time: f32, level: f32) -> Self {
+        Self::new(time, level, 0.0)
+    }
+
+    /// The level at `t` between 0 and 1 through the segment, starting from `from`.
+    fn sample(&self, from: f32, t: f32) -> f32 {
+        let t = t.clamp(0.0, 1.0);
+        let shaped = if self.curve.abs() < 1e-3 {
+            t
+        } else {
+            ((self.curve * t).exp() - 1.0) / (self.curve.exp() - 1.0)
+        };
+        from + (self.level - from) * shaped
+    }
+}
+
+/// A multi-segment envelope.
+///
+/// The note starts at 0 and runs through the segments up to and including the sustain
+/// segment, then holds its level. When the note is released, the segments after the sustain
+/// segment form the release tail, starting from whatever level the note is at. If there is no
+/// release tail, the note ends as soon as it is released.
+///
+/// With a loop start, the segments from the loop start up to the sustain segment repeat for as
+/// long as the note is held, instead of holding the sustain level.
+///
+/// A retriggered note starts the first segment from the level it is at.
+/// [`BreakpointEnvelope::dahdsr`] shows a typical layout.
+#[derive(Debug, Clone)]
+pub struct BreakpointEnvelope {
+    pub segments: Vec<Segment>,
+    /// The index of the segment after which the level is held.
+    pub sustain: usize,
+    /// The index of the first segment repeated while the note is held, if any.
+    pub loop_start: Option<usize>,
+}
+
+impl BreakpointEnvelope {
+    pub fn new(segments: Vec<Segment>, sustain: usize) -> Self {
+        Self {
+            segments,
+            sustain,
+            loop_start: None,
+        }
+    }
+
+    /// Loop the segments from `loop_start` up to the sustain segment while the note is held.
+    pub fn with_loop(mut self, loop_start: usize) -> Self {
+        self.loop_start = Some(loop_start);
+        self
+    }
+
+    /// A linear delay, attack, hold, decay, sustain, release envelope. All times are in seconds.
+    pub fn dahdsr(
+        delay: f32,
+        attack: f32,
+        hold: f32,
+        decay: f32,
+        sustain: f32,
+        release: f32,
+    ) -> Self {
+        Self::new(
+            vec![
+                Segment::linear(delay, 0.0),
+                Segment::linear(attack, 1.0),
+                Segment::linear(hold, 1.0),
+                Segment::linear(decay, sustain),
+                Segment::linear(release, 0.0),
+            ],
+            3,
+        )
+    }
+
+    /// The sustain segment, clamped to the existing segments.
+    fn sustain(&self) -> usize {
+        self.sustain.min(self.segments.len().saturating_sub(1))
+    }
+
+    /// The segment the loop jumps back to, if the loop is usable. Loops of zero length would
+    /// never advance, so they hold the sustain level instead.
+    fn loop_start(&self) -> Option<usize> {
+        let start = self.loop_start.filter(|start| *start <= self.sustain())?;
+        let duration = self.segments[start..=self.sustain()]
+            .iter()
+            .map(|s| s.time)
+            .sum::<f32>();
+        (duration > 0.0).then_some(start)
+    }
+
+    fn release_time(&self) -> f32 {
+        self.segments
+            .iter()
+            .skip(self.sustain() + 1)
+            .map(|s| s.time)
+            .sum()
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct BreakpointEnvelopeState {
+    /// The segment currently playing. While held, a segment past the sustain segment means the
+    /// sustain level is being held; once released, one past the last means the note is over.
+    segment: usize,
+    /// When the current segment started, in time held or time since release.
+    segment_start: f32,
+    /// The level the current segment started from.
+    from: f32,
+    /// The last sampled level.
+    level: f32,
+    released: bool,
+}
+
+impl Envelope for BreakpointEnvelope {
+    type State = BreakpointEnvelopeState;
+
+    fn create_state(&self) -> Self::State {
+        BreakpointEnvelopeState::default()
+    }
+
+    fn sample(&self, state: &mut Self::State, note: NoteContext) -> f32 {
+        if self.segments.is_empty() {
+            return 0.0;
+        }
+        let sustain = self.sustain();
+        let (time, last) = match note.time_released {
+            None => (note.time_held, sustain),
+            Some(time) => {
+                if !state.released {
+                    state.released = true;
+                    state.segment = sustain + 1;
+                    state.segment_start = 0.0;
+                    // Without a release tail, the note falls silent right away.
+                    let has_tail = sustain + 1 < self.segments.len();
+                    state.from = if has_tail { state.level } else { 0.0 };
+                }
+                (time, self.segments.len() - 1)
+            }
+        };
+
+        // Move on to the segment containing `time`.
+        while state.segment <= last {
+            let segment = &self.segments[state.segment];
+            if time < state.segment_start + segment.time {
+                break;
+            }
+            state.segment_start += segment.time;
+            state.from = segment.level;
+            state.segment = match self.loop_start() {
+                Some(loop_start) if !state.released && state.segment == sustain => loop_start,
+                _ => state.segment + 1,
+            };
+        }
+
+        state.level = match self.segments.get(state.segment) {
+            Some(segment) if state.segment <= last => {
+                let t = (time - state.segment_start) / segment.time;
+                segment.sample(state.from, t)
+            }
+            // Holding the sustain level, or past the end of the release.
+            _ => state.from,
+        };
+        state.level
+    }
+
+    fn note_ended(&self, _state: &Self::State, note: NoteContext) -> bool {
+        note.time_released
+            .is_some_and(|time| time >= self.release_time())
+    }
+
+    fn retrigger(&self, state: &mut Self::State) {
+        *state = BreakpointEnvelopeState {
+            from: state.level,
+            level: state.level,
+            ..Default::default()
+        };
+    }
+}
+
+#[test]
+fn test_breakpoint_envelope() {
+    let note = NoteContext::new(440.0, 1.0, 44100.0);
+    let sample = |env: &BreakpointEnvelope, times: &[f32]| {
+        let mut state = env.create_state();
+        times
+            .iter()
+            .map(|t| env.sample(&mut state, note.with_time_held(*t)))
+            .collect::<Vec<_>>()
+    };
+
+    let env = BreakpointEnvelope::dahdsr(1.0, 1.0, 1.0, 1.0, 0.5, 1.0);
+    assert_eq!(
+        sample(&env, &[0.5, 1.5, 2.5, 3.5, 10.0]),
+        [0.0, 0.5, 1.0, 0.75, 0.5]
+    );
+
+    // A rise and fall looping every two seconds.
+    let looping = BreakpointEnvelope::new(
+        vec![
+            Segment::linear(1.0, 1.0),
+            Segment::linear(1.0, 0.0),
+            Segment::linear(1.0, 0.0),
+        ],
+        1,
+    )
+    .with_loop(0);
+    assert_eq!(
+        sample(&looping, &[0.5, 1.5, 2.5, 3.5, 4.5]),
+        [0.5, 0.5, 0.5, 0.5, 0.5]
+    );
+    assert_eq!(
+        sample(&looping, &[1.0, 2.0, 3.0, 4.0]),
+        [1.0, 0.0, 1.0, 0.0]
+    );
+
+    // Released mid-attack, the release starts from the current level.
+    let mut state = env.create_state();
+    env.sample(&mut state, note.with_time_held(1.5));
+    let released = note.with_time_held(1.5);
+    assert_eq!(
+        env.sample(&mut state, released.with_time_released(0.5)),
+        0.25
+    );
+    assert!(env.note_ended(&state, released.with_time_released(1.0)));
+}
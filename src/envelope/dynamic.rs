@@ -0,0 +1,135 @@
+//! Type-erased envelopes, for choosing the envelope at runtime.
+//!
+//! Every envelope that is `Send` and whose state is `Send + 'static` implements the
+//! object-safe [`DynEnvelope`], which keeps its state boxed as `dyn Any`.
+//! `Box<dyn DynEnvelope>` implements [`Envelope`], mirroring
+//! [`osc::dynamic`](crate::osc::dynamic).
+
+use std::any::Any;
+
+use super::{Envelope, NoteContext};
+
+/// The erased state of a [`DynEnvelope`].
+pub type DynState = Box<dyn Any + Send>;
+
+/// An object-safe version of [`Envelope`]. See the [module documentation](self).
+pub trait DynEnvelope: Send {
+    fn create_state_dyn(&self) -> DynState;
+
+    /// See [`Envelope::sample`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if `state` was not created by this envelope.
+    fn sample_dyn(&self, state: &mut DynState, note: NoteContext) -> f32;
+
+    /// See [`Envelope::note_ended`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if `state` was not created by this envelope.
+    fn note_ended_dyn(&self, state: &DynState, note: NoteContext) -> bool;
+
+    /// See [`Envelope::retrigger`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if `state` was not created by this envelope.
+    fn retrigger_dyn(&self, state: &mut DynState);
+}
+
+const WRONG_STATE: &str = "envelope state was created by a different envelope";
+
+impl<E> DynEnvelope for E
+where
+    E: Envelope + Send,
+    E::State: Send + 'static,
+{
+    fn create_state_dyn(&self) -> DynState {
+        Box::new(self.create_state())
+    }
+
+    fn sample_dyn(&self, state: &mut DynState, note: NoteContext) -> f32 {
+        self.sample(state.downcast_mut().expect(WRONG_STATE), note)
+    }
+
+    fn note_ended_dyn(&self, state: &DynState, note: NoteContext) -> bool {
+        self.note_ended(state.downcast_ref().expect(WRONG_STATE), note)
+    }
+
+    fn retrigger_dyn(&self, state: &mut DynState) {
+        self.retrigger(state.downcast_mut().expect(WRONG_STATE));
+    }
+}
+
+impl Envelope for Box<dyn DynEnvelope> {
+    type State = DynState;
+
+    fn create_state(&self) -> Self::State {
+        self.as_ref().create_state_dyn()
+    }
+
+    fn sample(&self, state: &mut Self::State, note: NoteContext) -> f32 {
+        self.as_ref().sample_dyn(state, note)
+    }
+
+    fn note_ended(&self, state: &Self::State, note: NoteContext) -> bool {
+        self.as_ref().note_ended_dyn(state, note)
+    }
+
+    fn retrigger(&self, state: &mut Self::State) {
+        self.as_ref().retrigger_dyn(state);
+    }
+}
+
+#[test]
+fn test_dyn_synth_plays_different_envelopes() {
+    use super::{adsr::AdsrEnvelope, breakpoint::BreakpointEnvelope};
+    use crate::{
+        osc::{sine::SineOscillator, Oscillator},
+        Config, DynSynth, Synth,
+    };
+
+    // Hold a note for 20 ms and release it for another 20 ms.
+    fn play<Osc: Oscillator, Env: Envelope>(synth: &mut Synth<Osc, Env>) -> Vec<f32> {
+        let id = synth.start_note(440.0, 1.0);
+        let mut out = vec![0.0; 4 * 441];
+        let (held, released) = out.split_at_mut(2 * 441);
+        synth.render(held);
+        synth.end_note(id);
+        synth.render(released);
+        out
+    }
+    let adsr = || AdsrEnvelope::new(0.01, 0.01, 0.5, 0.01);
+    let breakpoint = || BreakpointEnvelope::dahdsr(0.005, 0.01, 0.0, 0.01, 0.3, 0.01);
+
+    let mut synth: DynSynth = Synth::new(
+        Config::default(),
+        Box::new(SineOscillator::default()),
+        Box::new(adsr()),
+        1,
+    );
+    let with_adsr = play(&mut synth);
+    assert_eq!(
+        with_adsr,
+        play(&mut Synth::new(
+            Config::default(),
+            SineOscillator::default(),
+            adsr(),
+            1
+        ))
+    );
+
+    synth.set_patch(Box::new(SineOscillator::default()), Box::new(breakpoint()));
+    let with_breakpoint = play(&mut synth);
+    assert_eq!(
+        with_breakpoint,
+        play(&mut Synth::new(
+            Config::default(),
+            SineOscillator::default(),
+            breakpoint(),
+            1
+        ))
+    );
+    assert_ne!(with_adsr, with_breakpoint);
+}
@@ -1,11 +1,139 @@
-use crate::note::NoteState;
+/// What an envelope knows about the note it is sampled for.
+///
+/// Envelopes outside this crate can implement [`Envelope`] using any of these; the built-in
+/// ones only look at the times.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NoteContext {
+    /// How long the note has been held, in seconds. It stops advancing when the note is released.
+    pub time_held: f32,
+    /// How long ago the note was released, in seconds, or `None` while it is held.
+    pub time_released: Option<f32>,
+    /// The amplitude the note was started with.
+    pub velocity: f32,
+    /// The current frequency of the note, in Hz.
+    pub freq: f32,
+    /// The sample rate the note is rendered at, in Hz.
+    pub sample_rate: f32,
+}
+
+impl NoteContext {
+    /// The context of a note that has just started.
+    pub fn new(freq: f32, velocity: f32, sample_rate: f32) -> Self {
+        Self {
+            time_held: 0.0,
+            time_released: None,
+            velocity,
+            freq,
+            sample_rate,
+        }
+    }
+
+    pub fn with_time_held(mut self, time_held: f32) -> Self {
+        self.time_held = time_held;
+        self
+    }
 
+    pub fn with_time_released(mut self, time_released: f32) -> Self {
+        self.time_released = Some(time_released);
+        self
+    }
+
+    pub fn is_held(&self) -> bool {
+        self.time_released.is_none()
+    }
+}
+
+/// Shapes the amplitude of every note over time.
+///
+/// Crates using this one can write their own envelopes. This one decays faster for higher notes
+/// and keeps the level it was released at in its state:
+///
+/// ```
+/// use happy_synth::envelope::{Envelope, NoteContext};
+/// use happy_synth::osc::sine::SineOscillator;
+/// use happy_synth::{Config, Synth};
+///
+/// struct Pluck {
+///     /// The decay time of a 440 Hz note, in seconds.
+///     decay: f32,
+/// }
+///
+/// struct PluckState {
+///     released_from: Option<f32>,
+/// }
+///
+/// impl Envelope for Pluck {
+///     type State = PluckState;
+///
+///     fn create_state(&self) -> PluckState {
+///         PluckState { released_from: None }
+///     }
+///
+///     fn sample(&self, state: &mut PluckState, note: NoteContext) -> f32 {
+///         let decay = self.decay * 440.0 / note.freq;
+///         let level = note.velocity * (-note.time_held / decay).exp();
+///         let Some(time) = note.time_released else {
+///             return level;
+///         };
+///         // Fade out over 10 ms from the level at the release.
+///         let from = *state.released_from.get_or_insert(level);
+///         from * (1.0 - time / 0.01).max(0.0)
+///     }
+///
+///     fn note_ended(&self, _state: &PluckState, note: NoteContext) -> bool {
+///         note.time_released.is_some_and(|time| time >= 0.01)
+///     }
+/// }
+///
+/// let env = Pluck { decay: 0.5 };
+/// let mut state = env.create_state();
+/// let note = NoteContext::new(880.0, 0.5, 44100.0);
+/// assert!(note.is_held());
+/// assert_eq!(env.sample(&mut state, note), 0.5);
+/// // Halfway through the release, the level is half of what it was released at.
+/// let released = note.with_time_released(0.005);
+/// assert!(!released.is_held());
+/// assert!((env.sample(&mut state, released) - 0.25).abs() < 1e-6);
+/// let later = note.with_time_held(1.0).with_time_released(0.005);
+/// assert!((env.sample(&mut state, later) - 0.25).abs() < 1e-6);
+///
+/// let mut synth = Synth::new(Config::default(), SineOscillator::default(), env, 4);
+/// let id = synth.start_note(440.0, 1.0);
+/// let mut buffer = vec![0.0; 441];
+/// synth.render(&mut buffer);
+/// assert!(buffer.iter().any(|x| x.abs() > 0.5));
+///
+/// // After the release fades out, the note is removed.
+/// synth.end_note(id);
+/// for _ in 0..2 {
+///     synth.render(&mut buffer);
+/// }
+/// synth.bookkeeping();
+/// buffer.fill(0.0);
+/// synth.render(&mut buffer);
+/// assert!(buffer.iter().all(|x| *x == 0.0));
+/// ```
 pub trait Envelope {
-    /// Sample the envelope at a given time.
-    fn sample(&self, state: NoteState) -> f32;
+    /// The per-note state of the envelope, e.g. the level at which the note was released.
+    type State;
+
+    /// Create the state for a new note.
+    fn create_state(&self) -> Self::State;
+
+    /// Sample the envelope at the note's current time. Samples are taken in order, so the state
+    /// can track the envelope's progress, e.g. to release from the level the note was at.
+    fn sample(&self, state: &mut Self::State, note: NoteContext) -> f32;
 
     /// Returns whether the note will not make any sound anymore.
-    fn note_ended(&self, state: NoteState) -> bool;
+    fn note_ended(&self, state: &Self::State, note: NoteContext) -> bool;
+
+    /// Restart the envelope of a note that is already playing. By default, the envelope starts
+    /// over as for a new note; envelopes that track their level should continue from it instead.
+    fn retrigger(&self, state: &mut Self::State) {
+        *state = self.create_state();
+    }
 }
 
 pub mod adsr;
+pub mod breakpoint;
+pub mod dynamic;
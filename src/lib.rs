@@ -2,9 +2,10 @@ pub mod envelope;
 mod note;
 pub mod osc;
 
-use envelope::Envelope;
+use envelope::{dynamic::DynEnvelope, Envelope};
 use note::Note;
-use osc::Oscillator;
+pub use note::NoteId;
+use osc::{dynamic::DynOscillator, Oscillator};
 
 pub struct Config {
     /// The sample rate of the audio stream, in Hz.
@@ -30,7 +31,16 @@ impl Default for Config {
     }
 }
 
-pub struct Synth<Osc: Oscillator, Env> {
+/// A sine LFO applied to the pitch of every note.
+#[derive(Debug, Clone)]
+pub struct Vibrato {
+    /// The LFO frequency, in Hz.
+    pub rate: f32,
+    /// How far the pitch swings in either direction, in cents.
+    pub depth: f32,
+}
+
+pub struct Synth<Osc: Oscillator, Env: Envelope> {
     /// The configuration of the synth.
     cfg: Config,
 
@@ -41,9 +51,15 @@ pub struct Synth<Osc: Oscillator, Env> {
     adsr: Env,
 
     /// Notes currently being played.
-    notes: note::NoteList<Osc::State>,
+    notes: note::NoteList<Osc::State, Env::State>,
+
+    /// The vibrato applied to all notes, if any.
+    vibrato: Option<Vibrato>,
 }
 
+/// A synth whose oscillator and envelope are chosen at runtime.
+pub type DynSynth = Synth<Box<dyn DynOscillator>, Box<dyn DynEnvelope>>;
+
 impl<Osc: Oscillator, Env: Envelope> Synth<Osc, Env> {
     pub fn new(cfg: Config, osc: Osc, adsr: Env, max_notes: usize) -> Self {
         Self {
@@ -51,25 +67,76 @@ impl<Osc: Oscillator, Env: Envelope> Synth<Osc, Env> {
             osc,
             adsr,
             notes: note::NoteList::new(max_notes),
+            vibrato: None,
         }
     }
 
-    pub fn start_note(&mut self, freq: f32, amp: f32) -> note::NoteId {
+    /// Replace the oscillator and the envelope. Notes currently being played are dropped, since
+    /// their state belongs to the old oscillator.
+    pub fn set_patch(&mut self, osc: Osc, adsr: Env) {
+        self.osc = osc;
+        self.adsr = adsr;
+        self.notes.clear();
+    }
+
+    /// The oscillator, for changing its parameters while notes are playing, e.g. the vowel of a
+    /// [`FormantOscillator`](osc::formant::FormantOscillator).
+    pub fn osc_mut(&mut self) -> &mut Osc {
+        &mut self.osc
+    }
+
+    pub fn set_vibrato(&mut self, vibrato: Option<Vibrato>) {
+        self.vibrato = vibrato;
+    }
+
+    pub fn start_note(&mut self, freq: f32, amp: f32) -> NoteId {
         let note = Note {
             freq,
             amp,
-            time: 0.0,
-            held: true,
+            target_freq: freq,
+            target_amp: amp,
+            vibrato_phase: 0.0,
+            velocity: amp,
+            time_held: 0.0,
+            time_released: None,
             state: self.osc.create_state(),
+            envelope: self.adsr.create_state(),
         };
         // note list helps maintain the capacity of notes
         self.notes.add(note)
     }
 
-    pub fn end_note(&mut self, id: note::NoteId) {
+    /// Change the frequency of a playing note, e.g. for pitch bends. The frequency glides to the
+    /// new value over the next rendered buffer.
+    pub fn set_note_freq(&mut self, id: NoteId, freq: f32) {
+        if let Some(note) = self.notes.get_mut(id) {
+            note.target_freq = freq;
+        }
+    }
+
+    /// Change the amplitude of a playing note. The amplitude ramps to the new value over the
+    /// next rendered buffer.
+    pub fn set_note_amp(&mut self, id: NoteId, amp: f32) {
+        if let Some(note) = self.notes.get_mut(id) {
+            note.target_amp = amp;
+        }
+    }
+
+    /// Restart the envelope of a playing note, e.g. for a new note on a monophonic synth. The
+    /// envelope continues from the level the note is at instead of starting from silence. Pair
+    /// this with [`Synth::set_note_freq`] to glide to the new pitch; for legato, change the
+    /// frequency without retriggering.
+    pub fn retrigger_note(&mut self, id: NoteId) {
         if let Some(note) = self.notes.get_mut(id) {
-            note.held = false;
-            note.time = 0.0;
+            self.adsr.retrigger(&mut note.envelope);
+            note.time_held = 0.0;
+            note.time_released = None;
+        }
+    }
+
+    pub fn end_note(&mut self, id: NoteId) {
+        if let Some(note) = self.notes.get_mut(id) {
+            note.time_released = Some(0.0);
         }
     }
 
@@ -77,21 +144,134 @@ impl<Osc: Oscillator, Env: Envelope> Synth<Osc, Env> {
         let delta_t = 1.0 / self.cfg.sample_rate;
         let total_time = buffer.len() as f32 * delta_t;
         let mut temp_buf = vec![0.0; buffer.len()];
+        let mut freqs = vec![];
+        let mut amps = vec![];
 
         for note in self.notes.notes_mut() {
-            self.osc
-                .fill_samples(&mut note.state, &mut temp_buf, delta_t, note.freq, note.amp);
+            temp_buf.fill(0.0);
+            let modulated = self.vibrato.is_some()
+                || note.freq != note.target_freq
+                || note.amp != note.target_amp;
+            if modulated {
+                fill_modulation(
+                    note,
+                    self.vibrato.as_ref(),
+                    delta_t,
+                    &mut freqs,
+                    &mut amps,
+                    buffer.len(),
+                );
+                self.osc.fill_samples_modulated(
+                    &mut note.state,
+                    &mut temp_buf,
+                    delta_t,
+                    &freqs,
+                    &amps,
+                );
+            } else {
+                self.osc
+                    .fill_samples(&mut note.state, &mut temp_buf, delta_t, note.freq, note.amp);
+            }
             for (i, (out, sample)) in buffer.iter_mut().zip(temp_buf.iter()).enumerate() {
                 let curr_time = i as f32 * delta_t;
-                let amp = self.adsr.sample(note.held_state(curr_time));
+                let context = note.context(curr_time, self.cfg.sample_rate);
+                let amp = self.adsr.sample(&mut note.envelope, context);
                 *out += *sample * amp;
             }
-            note.time += total_time;
+            note.advance(total_time);
         }
     }
 
     pub fn bookkeeping(&mut self) {
-        self.notes
-            .filter(|n| !self.adsr.note_ended(n.held_state(0.0)));
+        let sample_rate = self.cfg.sample_rate;
+        self.notes.filter(|n| {
+            !self
+                .adsr
+                .note_ended(&n.envelope, n.context(0.0, sample_rate))
+        });
+    }
+}
+
+/// Compute the per-sample frequency and amplitude of a note for the next `len` samples, ramping
+/// towards its targets and applying vibrato. The note is left at its targets.
+fn fill_modulation<St, Es>(
+    note: &mut Note<St, Es>,
+    vibrato: Option<&Vibrato>,
+    delta_t: f32,
+    freqs: &mut Vec<f32>,
+    amps: &mut Vec<f32>,
+    len: usize,
+) {
+    freqs.clear();
+    amps.clear();
+    for i in 0..len {
+        let t = (i + 1) as f32 / len as f32;
+        let mut freq = note.freq + (note.target_freq - note.freq) * t;
+        if let Some(vibrato) = vibrato {
+            let lfo = (2.0 * std::f32::consts::PI * note.vibrato_phase).sin();
+            freq *= 2.0_f32.powf(lfo * vibrato.depth / 1200.0);
+            note.vibrato_phase += vibrato.rate * delta_t;
+            note.vibrato_phase %= 1.0;
+        }
+        freqs.push(freq);
+        amps.push(note.amp + (note.target_amp - note.amp) * t);
+    }
+    note.freq = note.target_freq;
+    note.amp = note.target_amp;
+}
+
+#[test]
+fn test_glide_and_vibrato() {
+    use envelope::adsr::AdsrEnvelope;
+    use osc::sine::SineOscillator;
+
+    let sample_rate = 44100.0;
+    // The frequency around sample `at`, from the upward zero crossings before and after it.
+    let freq_at = |samples: &[f32], at: usize| {
+        let crossing = |ix: usize| {
+            let (a, b) = (samples[ix], samples[ix + 1]);
+            (a <= 0.0 && b > 0.0).then(|| ix as f32 + a / (a - b))
+        };
+        let before = (0..at).rev().find_map(crossing).unwrap();
+        let after = (at..samples.len() - 1).find_map(crossing).unwrap();
+        sample_rate / (after - before)
+    };
+    let synth = || {
+        Synth::new(
+            Config::default(),
+            SineOscillator::default(),
+            AdsrEnvelope::new(0.0, 0.0, 1.0, 0.1),
+            1,
+        )
+    };
+
+    // A glide from 440 Hz to 880 Hz over the second buffer, without any jumps.
+    let mut glide = synth();
+    let note = glide.start_note(440.0, 1.0);
+    let mut out = vec![0.0; 3 * 2205];
+    glide.render(&mut out[..2205]);
+    glide.set_note_freq(note, 880.0);
+    glide.render(&mut out[2205..4410]);
+    glide.render(&mut out[4410..]);
+    assert!((freq_at(&out, 1100) - 440.0).abs() < 1.0);
+    assert!((freq_at(&out, 5500) - 880.0).abs() < 1.0);
+    let max_step = 2.0 * std::f32::consts::PI * 880.0 / sample_rate;
+    for pair in out.windows(2) {
+        assert!((pair[1] - pair[0]).abs() <= max_step * 1.01);
+    }
+
+    // A semitone of vibrato at 5 Hz peaks after 50 ms and bottoms out after 150 ms.
+    let mut vibrato = synth();
+    vibrato.set_vibrato(Some(Vibrato {
+        rate: 5.0,
+        depth: 100.0,
+    }));
+    vibrato.start_note(440.0, 1.0);
+    let mut out = vec![0.0; 8820];
+    for buffer in out.chunks_mut(DEFAULT_BUFFER_SIZE) {
+        vibrato.render(buffer);
     }
+    let semitone = 2.0_f32.powf(1.0 / 12.0);
+    assert!((freq_at(&out, 2205) - 440.0 * semitone).abs() < 2.0);
+    assert!((freq_at(&out, 6615) - 440.0 / semitone).abs() < 2.0);
 }
@@ -2,30 +2,52 @@
 
 use slotmap::SlotMap;
 
-pub struct Note<State> {
+use crate::envelope::NoteContext;
+
+pub struct Note<State, EnvState> {
     /// The frequency of the note.
     pub freq: f32,
     /// The amplitude of the note.
     pub amp: f32,
-    /// The time since the note started or was released, depending on `held`.
-    pub time: f32,
-    /// Whether the node is still being held.
-    pub held: bool,
+    /// The frequency the note glides to during the next rendered buffer.
+    pub target_freq: f32,
+    /// The amplitude the note ramps to during the next rendered buffer.
+    pub target_amp: f32,
+    /// The phase of the vibrato LFO, between 0 and 1.
+    pub vibrato_phase: f32,
+    /// The amplitude the note was started with.
+    pub velocity: f32,
+    /// How long the note has been held, in seconds.
+    pub time_held: f32,
+    /// How long ago the note was released, in seconds, or `None` while it is held.
+    pub time_released: Option<f32>,
     /// The state of the oscillator.
     pub state: State,
+    /// The state of the envelope.
+    pub envelope: EnvState,
 }
 
-pub enum NoteState {
-    Holding(f32),
-    Released(f32),
-}
+impl<St, Es> Note<St, Es> {
+    /// The context passed to the envelope, `t_offset` seconds into the next buffer.
+    pub fn context(&self, t_offset: f32, sample_rate: f32) -> NoteContext {
+        let (time_held, time_released) = match self.time_released {
+            None => (self.time_held + t_offset, None),
+            Some(time) => (self.time_held, Some(time + t_offset)),
+        };
+        NoteContext {
+            time_held,
+            time_released,
+            velocity: self.velocity,
+            freq: self.freq,
+            sample_rate,
+        }
+    }
 
-impl<St> Note<St> {
-    pub fn held_state(&self, t_offset: f32) -> NoteState {
-        if self.held {
-            NoteState::Holding(self.time + t_offset)
-        } else {
-            NoteState::Released(self.time + t_offset)
+    /// Advance the note's time by `time` seconds.
+    pub fn advance(&mut self, time: f32) {
+        match &mut self.time_released {
+            None => self.time_held += time,
+            Some(released) => *released += time,
         }
     }
 }
@@ -34,19 +56,19 @@ slotmap::new_key_type! {
     pub struct NoteId;
 }
 
-struct ListEntry<St> {
-    it: Note<St>,
+struct ListEntry<St, Es> {
+    it: Note<St, Es>,
     next: Option<NoteId>,
     prev: Option<NoteId>,
 }
 
-pub struct NoteList<St> {
+pub struct NoteList<St, Es> {
     head: Option<NoteId>,
     tail: Option<NoteId>,
-    entries: SlotMap<NoteId, ListEntry<St>>,
+    entries: SlotMap<NoteId, ListEntry<St, Es>>,
 }
 
-impl<St> NoteList<St> {
+impl<St, Es> NoteList<St, Es> {
     pub fn new(cap: usize) -> Self {
         NoteList {
             head: None,
@@ -55,7 +77,7 @@ impl<St> NoteList<St> {
         }
     }
 
-    pub fn add(&mut self, note: Note<St>) -> NoteId {
+    pub fn add(&mut self, note: Note<St, Es>) -> NoteId {
         // Evict the oldest note if the list is full.
         if self.entries.len() == self.entries.capacity() {
             let key = self.head.unwrap();
@@ -79,7 +101,7 @@ impl<St> NoteList<St> {
         key
     }
 
-    pub fn get_mut(&mut self, key: NoteId) -> Option<&mut Note<St>> {
+    pub fn get_mut(&mut self, key: NoteId) -> Option<&mut Note<St, Es>> {
         self.entries.get_mut(key).map(|entry| &mut entry.it)
     }
 
@@ -99,7 +121,7 @@ impl<St> NoteList<St> {
         }
     }
 
-    pub fn filter(&mut self, f: impl Fn(&Note<St>) -> bool) {
+    pub fn filter(&mut self, f: impl Fn(&Note<St, Es>) -> bool) {
         let mut key = self.head;
         while let Some(k) = key {
             let next = self.entries[k].next;
@@ -110,7 +132,13 @@ impl<St> NoteList<St> {
         }
     }
 
-    pub fn notes_mut(&mut self) -> impl Iterator<Item = &mut Note<St>> {
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.head = None;
+        self.tail = None;
+    }
+
+    pub fn notes_mut(&mut self) -> impl Iterator<Item = &mut Note<St, Es>> {
         self.entries.values_mut().map(|entry| &mut entry.it)
     }
 }
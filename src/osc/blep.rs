@@ -0,0 +1,32 @@
+//! Polynomial band-limited step helpers used by the anti-aliased oscillators.
+
+/// The two-sample polynomial approximation of a band-limited step residual.
+///
+/// `t` is the current phase in `0..1` and `dt` is the phase increment per sample. The
+/// returned value should be added to a naive waveform at every upward unit discontinuity
+/// (and subtracted at downward ones), scaled by half the jump height.
+pub(crate) fn poly_blep(t: f32, dt: f32) -> f32 {
+    if dt <= 0.0 {
+        return 0.0;
+    }
+    if t < dt {
+        let t = t / dt;
+        2.0 * t - t * t - 1.0
+    } else if t > 1.0 - dt {
+        let t = (t - 1.0) / dt;
+        t * t + 2.0 * t + 1.0
+    } else {
+        0.0
+    }
+}
+
+#[test]
+fn test_poly_blep() {
+    let dt = 0.1;
+    // The residual spans one unit jump across the discontinuity and vanishes elsewhere.
+    assert_eq!(poly_blep(0.0, dt), -1.0);
+    assert_eq!(poly_blep(1.0, dt), 1.0);
+    assert_eq!(poly_blep(0.5, dt), 0.0);
+    assert!(poly_blep(dt * 0.999, dt).abs() < 1e-3);
+    assert!(poly_blep(1.0 - dt * 0.999, dt).abs() < 1e-3);
+}
@@ -0,0 +1,100 @@
+//! The audio of the Game Boy (DMG).
+
+use crate::osc::Oscillator;
+
+use super::{quantize_freq, SequencerState};
+
+/// The rate of the wave channel's frequency clock, in Hz. The channel plays at
+/// `65536 / (2048 - x)` Hz for its 11-bit frequency register `x`.
+const WAVE_CLOCK: f32 = 65536.0;
+
+/// The wave channel, playing a user-defined cycle of 32 4-bit samples.
+#[derive(Debug, Clone)]
+pub struct GameBoyWaveOscillator {
+    /// The contents of wave RAM, one 4-bit sample per entry. Only the low 4 bits are used.
+    pub samples: [u8; 32],
+}
+
+impl GameBoyWaveOscillator {
+    pub fn new(samples: [u8; 32]) -> Self {
+        Self { samples }
+    }
+
+    /// Fill wave RAM with one cycle of another oscillator, quantized to 4 bits. The cycle is
+    /// scaled to use the full range.
+    pub fn from_oscillator<O: Oscillator>(osc: &O) -> Self {
+        let mut cycle = [0.0; 32];
+        let delta_t = 1.0 / cycle.len() as f32;
+        osc.fill_samples(&mut osc.create_state(), &mut cycle, delta_t, 1.0, 1.0);
+        let (min, max) = cycle
+            .iter()
+            .fold((f32::MAX, f32::MIN), |(lo, hi), &x| (lo.min(x), hi.max(x)));
+        let range = (max - min).max(f32::EPSILON);
+        Self {
+            samples: cycle.map(|x| ((x - min) / range * 15.0).round() as u8),
+        }
+    }
+
+    /// The frequency closest to `freq` that the wave channel can play.
+    pub fn quantize_freq(freq: f32) -> f32 {
+        // The frequency register counts up to 2048, so the period is `2048 - x` for x in
+        // 0..=2047.
+        quantize_freq(freq, WAVE_CLOCK, 0.0, (1, 2048))
+    }
+}
+
+impl Default for GameBoyWaveOscillator {
+    /// A 4-bit sawtooth, ramping up over the cycle.
+    fn default() -> Self {
+        Self::new(std::array::from_fn(|i| (i / 2) as u8))
+    }
+}
+
+impl Oscillator for GameBoyWaveOscillator {
+    type State = SequencerState;
+
+    fn create_state(&self) -> Self::State {
+        SequencerState::default()
+    }
+
+    fn create_state_at(&self, phase: f32) -> Self::State {
+        SequencerState::at(phase)
+    }
+
+    fn fill_samples(
+        &self,
+        state: &mut Self::State,
+        buffer: &mut [f32],
+        delta_t: f32,
+        freq: f32,
+        amp: f32,
+    ) {
+        let increment = Self::quantize_freq(freq) * delta_t;
+        state.render(&self.samples, buffer, increment, amp, |level| {
+            (level & 0xf) as f32 / 7.5 - 1.0
+        });
+    }
+}
+
+#[test]
+fn test_frequencies_snap_to_frequency_register() {
+    // A4 needs a period of about 148.9 clocks, so the register is set to 2048 - 149.
+    assert_eq!(
+        GameBoyWaveOscillator::quantize_freq(440.0),
+        WAVE_CLOCK / 149.0
+    );
+    for freq in [60.0, 261.63, 1000.0, 3000.0] {
+        let period = WAVE_CLOCK / GameBoyWaveOscillator::quantize_freq(freq);
+        assert!(
+            (period - period.round()).abs() < 1e-3,
+            "{} at {}",
+            period,
+            freq
+        );
+        assert!((period - WAVE_CLOCK / freq).abs() <= 0.5 + 1e-3);
+    }
+
+    // Out of range frequencies clamp to register values 0 and 2047.
+    assert_eq!(GameBoyWaveOscillator::quantize_freq(10.0), 32.0);
+    assert_eq!(GameBoyWaveOscillator::quantize_freq(100_000.0), WAVE_CLOCK);
+}
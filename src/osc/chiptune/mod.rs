@@ -0,0 +1,59 @@
+//! Emulations of the sound channels of classic game consoles.
+//!
+//! The channels are not band-limited, just like the hardware. Their frequency is derived from a
+//! clock divided by an integer timer period, so notes snap to the nearest pitch the hardware can
+//! produce; at high pitches this detunes them audibly, as it did on the real consoles.
+
+mod gameboy;
+mod nes;
+
+pub use gameboy::GameBoyWaveOscillator;
+pub use nes::{
+    NesDuty, NesNoiseMode, NesNoiseOscillator, NesNoiseOscillatorState, NesPulseOscillator,
+    NesTriangleOscillator,
+};
+
+/// The state of a channel stepping through a fixed sequence of levels.
+#[derive(Debug, Clone, Default)]
+pub struct SequencerState {
+    /// The position in the sequence, between 0 and 1.
+    phase: f32,
+}
+
+impl SequencerState {
+    fn at(phase: f32) -> Self {
+        Self {
+            phase: phase.rem_euclid(1.0),
+        }
+    }
+
+    /// Step through `sequence`, one full pass per cycle, mapping each value through `level`.
+    fn render<T: Copy>(
+        &mut self,
+        sequence: &[T],
+        buffer: &mut [f32],
+        increment: f32,
+        amp: f32,
+        level: impl Fn(T) -> f32,
+    ) {
+        let steps = sequence.len() as f32;
+        for sample in buffer {
+            let ix = ((self.phase * steps) as usize).min(sequence.len() - 1);
+            *sample += level(sequence[ix]) * amp;
+            self.phase += increment;
+            self.phase %= 1.0;
+        }
+    }
+}
+
+/// The frequency closest to `freq` that a channel can play, given that it plays at
+/// `clock / (period + offset)` for an integer `period` within `periods`.
+fn quantize_freq(freq: f32, clock: f32, offset: f32, periods: (u16, u16)) -> f32 {
+    if freq <= 0.0 {
+        return 0.0;
+    }
+    let period = (clock / freq - offset)
+        .round()
+        .clamp(periods.0 as f32, periods.1 as f32);
+    clock / (period + offset)
+}
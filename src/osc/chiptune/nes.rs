@@ -0,0 +1,313 @@
+//! The Ricoh 2A03 audio processing unit of the NES (NTSC).
+
+use crate::osc::Oscillator;
+
+use super::{quantize_freq, SequencerState};
+
+/// The CPU clock of an NTSC NES, in Hz. All APU timers count CPU cycles.
+const CPU_CLOCK: f32 = 1_789_773.0;
+
+/// The range of the 11-bit timer of the pulse and triangle channels. Pulse timers below 8
+/// silence the channel, so they are excluded for both.
+const TIMER_PERIODS: (u16, u16) = (8, 2047);
+
+/// The duty cycle of a pulse channel.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum NesDuty {
+    /// 12.5%
+    Eighth,
+    /// 25%
+    Quarter,
+    /// 50%
+    #[default]
+    Half,
+    /// 75%, which sounds the same as 25% with the phase inverted.
+    ThreeQuarters,
+}
+
+impl NesDuty {
+    /// The 8-step sequence output by the pulse channel.
+    fn sequence(self) -> [u8; 8] {
+        match self {
+            NesDuty::Eighth => [0, 1, 0, 0, 0, 0, 0, 0],
+            NesDuty::Quarter => [0, 1, 1, 0, 0, 0, 0, 0],
+            NesDuty::Half => [0, 1, 1, 1, 1, 0, 0, 0],
+            NesDuty::ThreeQuarters => [1, 0, 0, 1, 1, 1, 1, 1],
+        }
+    }
+}
+
+/// One of the two pulse channels.
+#[derive(Debug, Clone, Default)]
+pub struct NesPulseOscillator {
+    pub duty: NesDuty,
+}
+
+impl NesPulseOscillator {
+    pub fn new(duty: NesDuty) -> Self {
+        Self { duty }
+    }
+
+    /// The frequency closest to `freq` that the pulse channel can play.
+    pub fn quantize_freq(freq: f32) -> f32 {
+        quantize_freq(freq, CPU_CLOCK / 16.0, 1.0, TIMER_PERIODS)
+    }
+}
+
+impl Oscillator for NesPulseOscillator {
+    type State = SequencerState;
+
+    fn create_state(&self) -> Self::State {
+        SequencerState::default()
+    }
+
+    fn create_state_at(&self, phase: f32) -> Self::State {
+        SequencerState::at(phase)
+    }
+
+    fn fill_samples(
+        &self,
+        state: &mut Self::State,
+        buffer: &mut [f32],
+        delta_t: f32,
+        freq: f32,
+        amp: f32,
+    ) {
+        let increment = Self::quantize_freq(freq) * delta_t;
+        state.render(&self.duty.sequence(), buffer, increment, amp, |level| {
+            level as f32 * 2.0 - 1.0
+        });
+    }
+}
+
+/// The 32-step sequence of the triangle channel, a 4-bit triangle.
+const TRIANGLE: [u8; 32] = [
+    15, 14, 13, 12, 11, 10, 9, 8, 7, 6, 5, 4, 3, 2, 1, 0, 0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12,
+    13, 14, 15,
+];
+
+/// The triangle channel. It has no volume control on the hardware, so `amp` only scales the
+/// output.
+#[derive(Debug, Clone, Default)]
+pub struct NesTriangleOscillator;
+
+impl NesTriangleOscillator {
+    /// The frequency closest to `freq` that the triangle channel can play.
+    pub fn quantize_freq(freq: f32) -> f32 {
+        quantize_freq(freq, CPU_CLOCK / 32.0, 1.0, TIMER_PERIODS)
+    }
+}
+
+impl Oscillator for NesTriangleOscillator {
+    type State = SequencerState;
+
+    fn create_state(&self) -> Self::State {
+        SequencerState::default()
+    }
+
+    fn create_state_at(&self, phase: f32) -> Self::State {
+        SequencerState::at(phase)
+    }
+
+    fn fill_samples(
+        &self,
+        state: &mut Self::State,
+        buffer: &mut [f32],
+        delta_t: f32,
+        freq: f32,
+        amp: f32,
+    ) {
+        let increment = Self::quantize_freq(freq) * delta_t;
+        state.render(&TRIANGLE, buffer, increment, amp, |level| {
+            level as f32 / 7.5 - 1.0
+        });
+    }
+}
+
+/// The 16 timer periods of the noise channel, in CPU cycles.
+const NOISE_PERIODS: [u16; 16] = [
+    4, 8, 16, 32, 64, 96, 128, 160, 202, 254, 380, 508, 762, 1016, 2034, 4068,
+];
+
+/// The feedback mode of the noise channel's shift register.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum NesNoiseMode {
+    /// A 32767-step sequence that sounds like white noise.
+    #[default]
+    Long,
+    /// A 93-step sequence with a metallic, pitched tone.
+    Short,
+}
+
+impl NesNoiseMode {
+    /// The register bit XORed with bit 0 to produce the feedback.
+    fn tap(self) -> u16 {
+        match self {
+            NesNoiseMode::Long => 1,
+            NesNoiseMode::Short => 6,
+        }
+    }
+}
+
+/// The noise channel, a 15-bit linear feedback shift register.
+///
+/// The hardware offers only 16 noise rates. The note picks the rate at which a short-mode
+/// sequence plays closest to the note frequency, so short-mode notes are roughly in tune and
+/// long-mode notes get brighter with pitch.
+#[derive(Debug, Clone, Default)]
+pub struct NesNoiseOscillator {
+    pub mode: NesNoiseMode,
+}
+
+impl NesNoiseOscillator {
+    pub fn new(mode: NesNoiseMode) -> Self {
+        Self { mode }
+    }
+
+    /// The rate, in Hz, at which the shift register is clocked for a note frequency.
+    fn clock_rate(freq: f32) -> f32 {
+        let target = freq * 93.0;
+        NOISE_PERIODS
+            .iter()
+            .map(|&period| CPU_CLOCK / period as f32)
+            .min_by(|a, b| {
+                let dist = |rate: f32| (rate / target).ln().abs();
+                dist(*a).total_cmp(&dist(*b))
+            })
+            .unwrap()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct NesNoiseOscillatorState {
+    /// The shift register. It is loaded with 1 on power-up.
+    lfsr: u16,
+    /// The phase of the shift register clock, between 0 and 1.
+    clock: f32,
+}
+
+impl NesNoiseOscillatorState {
+    /// Clock the shift register once.
+    fn step(&mut self, mode: NesNoiseMode) {
+        let feedback = (self.lfsr ^ (self.lfsr >> mode.tap())) & 1;
+        self.lfsr = (self.lfsr >> 1) | (feedback << 14);
+    }
+}
+
+impl Oscillator for NesNoiseOscillator {
+    type State = NesNoiseOscillatorState;
+
+    fn create_state(&self) -> Self::State {
+        NesNoiseOscillatorState {
+            lfsr: 1,
+            clock: 0.0,
+        }
+    }
+
+    fn fill_samples(
+        &self,
+        state: &mut Self::State,
+        buffer: &mut [f32],
+        delta_t: f32,
+        freq: f32,
+        amp: f32,
+    ) {
+        if freq <= 0.0 {
+            return;
+        }
+        let increment = Self::clock_rate(freq) * delta_t;
+        for sample in buffer {
+            // The hardware is silent while bit 0 is set and outputs its volume otherwise. Here
+            // the two states are centered around 0 instead.
+            let out = if state.lfsr & 1 == 0 { 1.0 } else { -1.0 };
+            *sample += out * amp;
+
+            state.clock += increment;
+            while state.clock >= 1.0 {
+                state.clock -= 1.0;
+                state.step(self.mode);
+            }
+        }
+    }
+}
+
+#[test]
+fn test_short_noise_period() {
+    // The short mode sequence starting from power-up repeats every 93 steps.
+    let osc = NesNoiseOscillator::new(NesNoiseMode::Short);
+    let mut state = osc.create_state();
+    let start = state.lfsr;
+    let mut steps = 0;
+    loop {
+        state.step(osc.mode);
+        steps += 1;
+        if state.lfsr == start || steps > 40000 {
+            break;
+        }
+    }
+    assert_eq!(steps, 93);
+}
+
+#[test]
+fn test_frequencies_snap_to_timer_periods() {
+    // A4 lies between timer periods, and snaps to the nearest one.
+    let pulse = NesPulseOscillator::quantize_freq(440.0);
+    assert_eq!(pulse, CPU_CLOCK / (16.0 * (253.0 + 1.0)));
+    let triangle = NesTriangleOscillator::quantize_freq(440.0);
+    assert_eq!(triangle, CPU_CLOCK / (32.0 * (126.0 + 1.0)));
+
+    // Every quantized frequency is playable, and the nearest playable one.
+    for freq in [60.0, 261.63, 1000.0, 3000.0] {
+        for (quantized, divider) in [
+            (NesPulseOscillator::quantize_freq(freq), 16.0),
+            (NesTriangleOscillator::quantize_freq(freq), 32.0),
+        ] {
+            let timer = CPU_CLOCK / (divider * quantized) - 1.0;
+            assert!(
+                (timer - timer.round()).abs() < 1e-3,
+                "{} at {}",
+                timer,
+                freq
+            );
+            let exact = CPU_CLOCK / (divider * freq) - 1.0;
+            assert!((timer - exact).abs() <= 0.5 + 1e-3);
+        }
+    }
+}
+
+#[test]
+fn test_out_of_range_frequencies_clamp() {
+    assert_eq!(
+        NesPulseOscillator::quantize_freq(20.0),
+        CPU_CLOCK / (16.0 * 2048.0)
+    );
+    assert_eq!(
+        NesPulseOscillator::quantize_freq(20000.0),
+        CPU_CLOCK / (16.0 * 9.0)
+    );
+    assert_eq!(
+        NesTriangleOscillator::quantize_freq(10.0),
+        CPU_CLOCK / (32.0 * 2048.0)
+    );
+    assert_eq!(
+        NesTriangleOscillator::quantize_freq(20000.0),
+        CPU_CLOCK / (32.0 * 9.0)
+    );
+}
+
+#[test]
+fn test_pulse_plays_quantized_frequency() {
+    // 3 kHz snaps to a timer period of 36, which plays at about 3024.6 Hz.
+    let osc = NesPulseOscillator::default();
+    let mut buf = vec![0.0; 44100];
+    osc.fill_samples(
+        &mut osc.create_state(),
+        &mut buf,
+        1.0 / 44100.0,
+        3000.0,
+        1.0,
+    );
+    let rising_edges = buf.windows(2).filter(|pair| pair[0] < pair[1]).count() as f32;
+    let expected = CPU_CLOCK / (16.0 * 37.0);
+    assert!((rising_edges - expected).abs() < 2.0, "{}", rising_edges);
+}
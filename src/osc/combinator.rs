@@ -0,0 +1,649 @@
+//! Generic combinators building two-oscillator patches out of any pair of oscillators.
+
+use super::Oscillator;
+
+/// The frequency of a modulating oscillator.
+#[derive(Debug, Clone, Copy)]
+pub enum ModulatorFreq {
+    /// A multiple of the note frequency.
+    Ratio(f32),
+    /// A fixed frequency in Hz, regardless of the note.
+    Fixed(f32),
+}
+
+impl ModulatorFreq {
+    fn freq(self, note_freq: f32) -> f32 {
+        match self {
+            ModulatorFreq::Ratio(ratio) => note_freq * ratio,
+            ModulatorFreq::Fixed(freq) => freq,
+        }
+    }
+}
+
+impl Default for ModulatorFreq {
+    fn default() -> Self {
+        ModulatorFreq::Ratio(1.0)
+    }
+}
+
+/// The state of two oscillators combined into one.
+#[derive(Debug, Clone)]
+pub struct CombinedState<A, B> {
+    a: A,
+    b: B,
+    /// Scratch space for combinators that need the outputs separately.
+    buf_a: Vec<f32>,
+    buf_b: Vec<f32>,
+}
+
+impl<A, B> CombinedState<A, B> {
+    fn new(a: A, b: B) -> Self {
+        Self {
+            a,
+            b,
+            buf_a: vec![],
+            buf_b: vec![],
+        }
+    }
+
+    /// Render both oscillators into the scratch buffers at unit amplitude.
+    fn render<OA, OB>(&mut self, osc_a: (&OA, f32), osc_b: (&OB, f32), len: usize, delta_t: f32)
+    where
+        OA: Oscillator<State = A>,
+        OB: Oscillator<State = B>,
+    {
+        self.buf_a.clear();
+        self.buf_a.resize(len, 0.0);
+        self.buf_b.clear();
+        self.buf_b.resize(len, 0.0);
+        osc_a
+            .0
+            .fill_samples(&mut self.a, &mut self.buf_a, delta_t, osc_a.1, 1.0);
+        osc_b
+            .0
+            .fill_samples(&mut self.b, &mut self.buf_b, delta_t, osc_b.1, 1.0);
+    }
+
+    /// Like [`CombinedState::render`], with a frequency for every sample.
+    fn render_modulated<OA, OB>(
+        &mut self,
+        osc_a: (&OA, &[f32]),
+        osc_b: (&OB, &[f32]),
+        len: usize,
+        delta_t: f32,
+    ) where
+        OA: Oscillator<State = A>,
+        OB: Oscillator<State = B>,
+    {
+        self.buf_a.clear();
+        self.buf_a.resize(len, 0.0);
+        self.buf_b.clear();
+        self.buf_b.resize(len, 0.0);
+        let ones = vec![1.0; len];
+        osc_a
+            .0
+            .fill_samples_modulated(&mut self.a, &mut self.buf_a, delta_t, osc_a.1, &ones);
+        osc_b
+            .0
+            .fill_samples_modulated(&mut self.b, &mut self.buf_b, delta_t, osc_b.1, &ones);
+    }
+}
+
+/// Two oscillators mixed together, the second one optionally detuned.
+#[derive(Debug, Clone)]
+pub struct Mix<A, B> {
+    pub a: A,
+    pub b: B,
+    pub gain_a: f32,
+    pub gain_b: f32,
+    /// The detune of `b` relative to the note frequency, in cents.
+    pub detune_b: f32,
+}
+
+impl<A: Oscillator, B: Oscillator> Mix<A, B> {
+    pub fn new(a: A, b: B) -> Self {
+        Self {
+            a,
+            b,
+            gain_a: 0.5,
+            gain_b: 0.5,
+            detune_b: 0.0,
+        }
+    }
+
+    pub fn with_gains(mut self, gain_a: f32, gain_b: f32) -> Self {
+        self.gain_a = gain_a;
+        self.gain_b = gain_b;
+        self
+    }
+
+    pub fn with_detune(mut self, detune_b: f32) -> Self {
+        self.detune_b = detune_b;
+        self
+    }
+}
+
+impl<A: Oscillator, B: Oscillator> Oscillator for Mix<A, B> {
+    type State = CombinedState<A::State, B::State>;
+
+    fn create_state(&self) -> Self::State {
+        CombinedState::new(self.a.create_state(), self.b.create_state())
+    }
+
+    fn create_state_at(&self, phase: f32) -> Self::State {
+        CombinedState::new(self.a.create_state_at(phase), self.b.create_state_at(phase))
+    }
+
+    fn reset_phase(&self, state: &mut Self::State, phase: f32) {
+        self.a.reset_phase(&mut state.a, phase);
+        self.b.reset_phase(&mut state.b, phase);
+    }
+
+    fn fill_samples(
+        &self,
+        state: &mut Self::State,
+        buffer: &mut [f32],
+        delta_t: f32,
+        freq: f32,
+        amp: f32,
+    ) {
+        let freq_b = freq * 2.0_f32.powf(self.detune_b / 1200.0);
+        self.a
+            .fill_samples(&mut state.a, buffer, delta_t, freq, amp * self.gain_a);
+        self.b
+            .fill_samples(&mut state.b, buffer, delta_t, freq_b, amp * self.gain_b);
+    }
+
+    fn fill_samples_modulated(
+        &self,
+        state: &mut Self::State,
+        buffer: &mut [f32],
+        delta_t: f32,
+        freqs: &[f32],
+        amps: &[f32],
+    ) {
+        let detune = 2.0_f32.powf(self.detune_b / 1200.0);
+        let scaled = |xs: &[f32], k: f32| xs.iter().map(|x| x * k).collect::<Vec<_>>();
+        self.a.fill_samples_modulated(
+            &mut state.a,
+            buffer,
+            delta_t,
+            freqs,
+            &scaled(amps, self.gain_a),
+        );
+        self.b.fill_samples_modulated(
+            &mut state.b,
+            buffer,
+            delta_t,
+            &scaled(freqs, detune),
+            &scaled(amps, self.gain_b),
+        );
+    }
+}
+
+/// Ring modulation: the product of two oscillators.
+#[derive(Debug, Clone)]
+pub struct RingMod<A, B> {
+    pub a: A,
+    pub b: B,
+    /// The frequency of `b`. `a` always plays at the note frequency.
+    pub freq_b: ModulatorFreq,
+}
+
+impl<A: Oscillator, B: Oscillator> RingMod<A, B> {
+    pub fn new(a: A, b: B, freq_b: ModulatorFreq) -> Self {
+        Self { a, b, freq_b }
+    }
+}
+
+impl<A: Oscillator, B: Oscillator> Oscillator for RingMod<A, B> {
+    type State = CombinedState<A::State, B::State>;
+
+    fn create_state(&self) -> Self::State {
+        CombinedState::new(self.a.create_state(), self.b.create_state())
+    }
+
+    fn create_state_at(&self, phase: f32) -> Self::State {
+        CombinedState::new(self.a.create_state_at(phase), self.b.create_state_at(phase))
+    }
+
+    fn reset_phase(&self, state: &mut Self::State, phase: f32) {
+        self.a.reset_phase(&mut state.a, phase);
+        self.b.reset_phase(&mut state.b, phase);
+    }
+
+    fn fill_samples(
+        &self,
+        state: &mut Self::State,
+        buffer: &mut [f32],
+        delta_t: f32,
+        freq: f32,
+        amp: f32,
+    ) {
+        let freq_b = self.freq_b.freq(freq);
+        state.render((&self.a, freq), (&self.b, freq_b), buffer.len(), delta_t);
+        for ((sample, a), b) in buffer.iter_mut().zip(&state.buf_a).zip(&state.buf_b) {
+            *sample += a * b * amp;
+        }
+    }
+
+    fn fill_samples_modulated(
+        &self,
+        state: &mut Self::State,
+        buffer: &mut [f32],
+        delta_t: f32,
+        freqs: &[f32],
+        amps: &[f32],
+    ) {
+        let freqs_b = freqs[..buffer.len()]
+            .iter()
+            .map(|freq| self.freq_b.freq(*freq))
+            .collect::<Vec<_>>();
+        state.render_modulated((&self.a, freqs), (&self.b, &freqs_b), buffer.len(), delta_t);
+        let products = state.buf_a.iter().zip(&state.buf_b);
+        for ((sample, (a, b)), amp) in buffer.iter_mut().zip(products).zip(amps) {
+            *sample += a * b * amp;
+        }
+    }
+}
+
+/// Amplitude modulation of a carrier by a modulator, e.g. tremolo.
+///
+/// The output is `carrier * (1 + depth * modulator) / (1 + depth)`, so the carrier is never
+/// inverted and the peak amplitude stays at `amp`.
+#[derive(Debug, Clone)]
+pub struct AmpMod<Carrier, Mod> {
+    pub carrier: Carrier,
+    pub modulator: Mod,
+    pub freq: ModulatorFreq,
+    /// The modulation depth, between 0 (no modulation) and 1.
+    pub depth: f32,
+}
+
+impl<Carrier: Oscillator, Mod: Oscillator> AmpMod<Carrier, Mod> {
+    pub fn new(carrier: Carrier, modulator: Mod, freq: ModulatorFreq, depth: f32) -> Self {
+        Self {
+            carrier,
+            modulator,
+            freq,
+            depth,
+        }
+    }
+}
+
+impl<Carrier: Oscillator, Mod: Oscillator> Oscillator for AmpMod<Carrier, Mod> {
+    type State = CombinedState<Carrier::State, Mod::State>;
+
+    fn create_state(&self) -> Self::State {
+        CombinedState::new(self.carrier.create_state(), self.modulator.create_state())
+    }
+
+    fn create_state_at(&self, phase: f32) -> Self::State {
+        CombinedState::new(
+            self.carrier.create_state_at(phase),
+            self.modulator.create_state_at(phase),
+        )
+    }
+
+    fn reset_phase(&self, state: &mut Self::State, phase: f32) {
+        self.carrier.reset_phase(&mut state.a, phase);
+        self.modulator.reset_phase(&mut state.b, phase);
+    }
+
+    fn fill_samples(
+        &self,
+        state: &mut Self::State,
+        buffer: &mut [f32],
+        delta_t: f32,
+        freq: f32,
+        amp: f32,
+    ) {
+        let mod_freq = self.freq.freq(freq);
+        state.render(
+            (&self.carrier, freq),
+            (&self.modulator, mod_freq),
+            buffer.len(),
+            delta_t,
+        );
+        let norm = amp / (1.0 + self.depth);
+        for ((sample, c), m) in buffer.iter_mut().zip(&state.buf_a).zip(&state.buf_b) {
+            *sample += c * (1.0 + self.depth * m) * norm;
+        }
+    }
+
+    fn fill_samples_modulated(
+        &self,
+        state: &mut Self::State,
+        buffer: &mut [f32],
+        delta_t: f32,
+        freqs: &[f32],
+        amps: &[f32],
+    ) {
+        let mod_freqs = freqs[..buffer.len()]
+            .iter()
+            .map(|freq| self.freq.freq(*freq))
+            .collect::<Vec<_>>();
+        state.render_modulated(
+            (&self.carrier, freqs),
+            (&self.modulator, &mod_freqs),
+            buffer.len(),
+            delta_t,
+        );
+        let signals = state.buf_a.iter().zip(&state.buf_b);
+        for ((sample, (c, m)), amp) in buffer.iter_mut().zip(signals).zip(amps) {
+            *sample += c * (1.0 + self.depth * m) * amp / (1.0 + self.depth);
+        }
+    }
+}
+
+/// Hard sync: the slave restarts its cycle every time the master completes one.
+///
+/// The master sets the pitch, running at the note frequency, while the slave's frequency sets
+/// the timbre. Only the slave is audible unless `master_gain` is set.
+///
+/// The slave is restarted with [`Oscillator::reset_phase`], so whatever else its state holds,
+/// e.g. the random phase offsets of [`Unison`](super::unison::Unison) voices, is the same on
+/// every cycle.
+#[derive(Debug, Clone)]
+pub struct HardSync<Master, Slave> {
+    pub master: Master,
+    pub slave: Slave,
+    /// The frequency of the slave relative to the master.
+    pub ratio: f32,
+    /// The level at which the master itself is mixed into the output.
+    pub master_gain: f32,
+}
+
+impl<Master: Oscillator, Slave: Oscillator> HardSync<Master, Slave> {
+    pub fn new(master: Master, slave: Slave, ratio: f32) -> Self {
+        Self {
+            master,
+            slave,
+            ratio,
+            master_gain: 0.0,
+        }
+    }
+
+    pub fn with_master_gain(mut self, master_gain: f32) -> Self {
+        self.master_gain = master_gain;
+        self
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct HardSyncState<M, S> {
+    master: M,
+    slave: S,
+    /// The phase of the master, between 0 and 1.
+    phase: f32,
+    /// Scratch space for the slave frequencies and master amplitudes in the modulated path.
+    freqs: Vec<f32>,
+    amps: Vec<f32>,
+}
+
+impl<Master: Oscillator, Slave: Oscillator> Oscillator for HardSync<Master, Slave> {
+    type State = HardSyncState<Master::State, Slave::State>;
+
+    fn create_state(&self) -> Self::State {
+        self.create_state_at(0.0)
+    }
+
+    fn create_state_at(&self, phase: f32) -> Self::State {
+        let phase = phase.rem_euclid(1.0);
+        HardSyncState {
+            master: self.master.create_state_at(phase),
+            slave: self.slave.create_state_at(phase * self.ratio),
+            phase,
+            freqs: vec![],
+            amps: vec![],
+        }
+    }
+
+    fn reset_phase(&self, state: &mut Self::State, phase: f32) {
+        let phase = phase.rem_euclid(1.0);
+        self.master.reset_phase(&mut state.master, phase);
+        self.slave.reset_phase(&mut state.slave, phase * self.ratio);
+        state.phase = phase;
+    }
+
+    fn fill_samples(
+        &self,
+        state: &mut Self::State,
+        buffer: &mut [f32],
+        delta_t: f32,
+        freq: f32,
+        amp: f32,
+    ) {
+        if self.master_gain != 0.0 {
+            self.master.fill_samples(
+                &mut state.master,
+                buffer,
+                delta_t,
+                freq,
+                amp * self.master_gain,
+            );
+        }
+
+        let increment = freq * delta_t;
+        let slave_freq = freq * self.ratio;
+        let mut rest = buffer;
+        while !rest.is_empty() {
+            // Render the slave up to the sample where the master wraps around.
+            let until_wrap = if increment > 0.0 {
+                ((1.0 - state.phase) / increment).ceil().max(1.0) as usize
+            } else {
+                usize::MAX
+            };
+            let len = until_wrap.min(rest.len());
+            let (segment, tail) = rest.split_at_mut(len);
+            self.slave
+                .fill_samples(&mut state.slave, segment, delta_t, slave_freq, amp);
+            rest = tail;
+
+            state.phase += increment * len as f32;
+            if state.phase >= 1.0 {
+                state.phase %= 1.0;
+                // Restart the slave where it would be if it was reset exactly at the wrap.
+                let slave_phase = state.phase * self.ratio;
+                self.slave.reset_phase(&mut state.slave, slave_phase);
+            }
+        }
+        self.sync_silent_master(state);
+    }
+
+    fn fill_samples_modulated(
+        &self,
+        state: &mut Self::State,
+        buffer: &mut [f32],
+        delta_t: f32,
+        freqs: &[f32],
+        amps: &[f32],
+    ) {
+        let len = buffer.len();
+        if self.master_gain != 0.0 {
+            state.amps.clear();
+            state
+                .amps
+                .extend(amps[..len].iter().map(|amp| amp * self.master_gain));
+            self.master.fill_samples_modulated(
+                &mut state.master,
+                buffer,
+                delta_t,
+                freqs,
+                &state.amps,
+            );
+        }
+
+        state.freqs.clear();
+        state
+            .freqs
+            .extend(freqs[..len].iter().map(|freq| freq * self.ratio));
+        let mut start = 0;
+        for (ix, freq) in freqs[..len].iter().enumerate() {
+            state.phase += freq * delta_t;
+            if state.phase >= 1.0 {
+                // Render the slave up to the sample where the master wraps around.
+                self.slave.fill_samples_modulated(
+                    &mut state.slave,
+                    &mut buffer[start..=ix],
+                    delta_t,
+                    &state.freqs[start..=ix],
+                    &amps[start..=ix],
+                );
+                start = ix + 1;
+                state.phase %= 1.0;
+                let slave_phase = state.phase * self.ratio;
+                self.slave.reset_phase(&mut state.slave, slave_phase);
+            }
+        }
+        self.slave.fill_samples_modulated(
+            &mut state.slave,
+            &mut buffer[start..],
+            delta_t,
+            &state.freqs[start..],
+            &amps[start..len],
+        );
+        self.sync_silent_master(state);
+    }
+}
+
+impl<Master: Oscillator, Slave: Oscillator> HardSync<Master, Slave> {
+    /// A silent master is not rendered, so move it to the tracked phase instead. Otherwise it
+    /// would resume from a stale phase when `master_gain` is raised.
+    fn sync_silent_master(&self, state: &mut HardSyncState<Master::State, Slave::State>) {
+        if self.master_gain == 0.0 {
+            self.master.reset_phase(&mut state.master, state.phase);
+        }
+    }
+}
+
+#[test]
+fn test_hard_sync_at_unison_is_transparent() {
+    use super::sine::SineOscillator;
+
+    let sync = HardSync::new(SineOscillator::default(), SineOscillator::default(), 1.0);
+    let mut sync_buf = vec![0.0; 1000];
+    let mut sine_buf = vec![0.0; 1000];
+    sync.fill_samples(
+        &mut sync.create_state(),
+        &mut sync_buf,
+        1.0 / 44100.0,
+        441.0,
+        1.0,
+    );
+    SineOscillator::default().fill_samples(
+        &mut SineOscillator::default().create_state(),
+        &mut sine_buf,
+        1.0 / 44100.0,
+        441.0,
+        1.0,
+    );
+    for (a, b) in sync_buf.iter().zip(&sine_buf) {
+        assert!((a - b).abs() < 1e-3, "{} != {}", a, b);
+    }
+}
+
+#[test]
+fn test_hard_sync_repeats_every_master_cycle() {
+    use super::{sine::SineOscillator, unison::Unison};
+
+    // A detuned unison slave with random phases, at a ratio that does not divide the cycle.
+    let sync = HardSync::new(
+        SineOscillator::default(),
+        Unison::new(SineOscillator::default(), 5, 30.0),
+        1.7,
+    );
+    let mut buf = vec![0.0; 1000];
+    // A master period of exactly 100 samples.
+    sync.fill_samples(
+        &mut sync.create_state(),
+        &mut buf,
+        1.0 / 44100.0,
+        441.0,
+        1.0,
+    );
+    for ix in 100..900 {
+        assert!(
+            (buf[ix] - buf[ix + 100]).abs() < 1e-3,
+            "{} != {} at {}",
+            buf[ix],
+            buf[ix + 100],
+            ix
+        );
+    }
+}
+
+#[test]
+fn test_modulated_fill_follows_steps() {
+    use super::{saw::SawOscillator, sine::SineOscillator};
+
+    // A frequency and amplitude step halfway through should match two separate buffers.
+    fn check<O: Oscillator>(osc: O) {
+        let delta_t = 1.0 / 44100.0;
+        let freqs = [[440.0; 100], [660.0; 100]].concat();
+        let amps = [[0.5; 100], [0.25; 100]].concat();
+        let mut scalar = vec![0.0; 200];
+        let mut state = osc.create_state();
+        let (first, second) = scalar.split_at_mut(100);
+        osc.fill_samples(&mut state, first, delta_t, 440.0, 0.5);
+        osc.fill_samples(&mut state, second, delta_t, 660.0, 0.25);
+        let mut modulated = vec![0.0; 200];
+        osc.fill_samples_modulated(
+            &mut osc.create_state(),
+            &mut modulated,
+            delta_t,
+            &freqs,
+            &amps,
+        );
+        for (a, b) in scalar.iter().zip(&modulated) {
+            assert!((a - b).abs() < 1e-5, "{} != {}", a, b);
+        }
+    }
+
+    check(RingMod::new(
+        SineOscillator::default(),
+        SawOscillator,
+        ModulatorFreq::Ratio(1.5),
+    ));
+    check(AmpMod::new(
+        SawOscillator,
+        SineOscillator::default(),
+        ModulatorFreq::Fixed(6.0),
+        0.5,
+    ));
+    check(HardSync::new(SineOscillator::default(), SawOscillator, 1.7).with_master_gain(0.5));
+}
+
+#[test]
+fn test_raising_master_gain_resumes_in_phase() {
+    use super::{saw::SawOscillator, sine::SineOscillator};
+
+    // The master is silent for the first half, then audible. Its second half should match a
+    // master that was audible all along, in both the scalar and the modulated path.
+    let delta_t = 1.0 / 44100.0;
+    let silent = HardSync::new(SineOscillator::default(), SawOscillator, 1.7);
+    let audible =
+        HardSync::new(SineOscillator::default(), SawOscillator, 1.7).with_master_gain(1.0);
+    for modulated in [false, true] {
+        let render = |osc: &HardSync<_, _>, state: &mut _, buffer: &mut [f32]| {
+            if modulated {
+                osc.fill_samples_modulated(state, buffer, delta_t, &[440.0; 150], &[1.0; 150]);
+            } else {
+                osc.fill_samples(state, buffer, delta_t, 440.0, 1.0);
+            }
+        };
+        let mut raised = vec![0.0; 300];
+        let mut state = silent.create_state();
+        let (first, second) = raised.split_at_mut(150);
+        render(&silent, &mut state, first);
+        render(&audible, &mut state, second);
+        let mut reference = vec![0.0; 300];
+        let mut state = audible.create_state();
+        let (first, second) = reference.split_at_mut(150);
+        render(&audible, &mut state, first);
+        render(&audible, &mut state, second);
+        for (a, b) in raised[150..].iter().zip(&reference[150..]) {
+            assert!((a - b).abs() < 1e-3, "{} != {}", a, b);
+        }
+    }
+}
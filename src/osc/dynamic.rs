@@ -0,0 +1,165 @@
+//! Type-erased oscillators, for choosing the oscillator at runtime.
+//!
+//! [`Oscillator`] has an associated state type and is therefore not object-safe. Every
+//! oscillator whose state is `Send + 'static` also implements the object-safe
+//! [`DynOscillator`], which keeps its state boxed as `dyn Any`. `Box<dyn DynOscillator>` in turn
+//! implements [`Oscillator`], so it can be used anywhere a concrete oscillator can.
+
+use std::any::Any;
+
+use super::Oscillator;
+
+/// The erased state of a [`DynOscillator`].
+pub type DynState = Box<dyn Any + Send>;
+
+/// An object-safe version of [`Oscillator`]. See the [module documentation](self).
+pub trait DynOscillator: Send {
+    fn create_state_dyn(&self) -> DynState;
+
+    fn create_state_at_dyn(&self, phase: f32) -> DynState;
+
+    /// See [`Oscillator::reset_phase`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if `state` was not created by this oscillator.
+    fn reset_phase_dyn(&self, state: &mut DynState, phase: f32);
+
+    /// See [`Oscillator::fill_samples`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if `state` was not created by this oscillator.
+    fn fill_samples_dyn(
+        &self,
+        state: &mut DynState,
+        buffer: &mut [f32],
+        delta_t: f32,
+        freq: f32,
+        amp: f32,
+    );
+
+    /// See [`Oscillator::fill_samples_modulated`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if `state` was not created by this oscillator.
+    fn fill_samples_modulated_dyn(
+        &self,
+        state: &mut DynState,
+        buffer: &mut [f32],
+        delta_t: f32,
+        freqs: &[f32],
+        amps: &[f32],
+    );
+}
+
+fn downcast<S: 'static>(state: &mut DynState) -> &mut S {
+    state
+        .downcast_mut::<S>()
+        .expect("oscillator state was created by a different oscillator")
+}
+
+impl<O> DynOscillator for O
+where
+    O: Oscillator + Send,
+    O::State: Send + 'static,
+{
+    fn create_state_dyn(&self) -> DynState {
+        Box::new(self.create_state())
+    }
+
+    fn create_state_at_dyn(&self, phase: f32) -> DynState {
+        Box::new(self.create_state_at(phase))
+    }
+
+    fn reset_phase_dyn(&self, state: &mut DynState, phase: f32) {
+        self.reset_phase(downcast(state), phase);
+    }
+
+    fn fill_samples_dyn(
+        &self,
+        state: &mut DynState,
+        buffer: &mut [f32],
+        delta_t: f32,
+        freq: f32,
+        amp: f32,
+    ) {
+        self.fill_samples(downcast(state), buffer, delta_t, freq, amp);
+    }
+
+    fn fill_samples_modulated_dyn(
+        &self,
+        state: &mut DynState,
+        buffer: &mut [f32],
+        delta_t: f32,
+        freqs: &[f32],
+        amps: &[f32],
+    ) {
+        self.fill_samples_modulated(downcast(state), buffer, delta_t, freqs, amps);
+    }
+}
+
+impl Oscillator for Box<dyn DynOscillator> {
+    type State = DynState;
+
+    fn create_state(&self) -> Self::State {
+        self.as_ref().create_state_dyn()
+    }
+
+    fn create_state_at(&self, phase: f32) -> Self::State {
+        self.as_ref().create_state_at_dyn(phase)
+    }
+
+    fn reset_phase(&self, state: &mut Self::State, phase: f32) {
+        self.as_ref().reset_phase_dyn(state, phase);
+    }
+
+    fn fill_samples(
+        &self,
+        state: &mut Self::State,
+        buffer: &mut [f32],
+        delta_t: f32,
+        freq: f32,
+        amp: f32,
+    ) {
+        self.as_ref()
+            .fill_samples_dyn(state, buffer, delta_t, freq, amp);
+    }
+
+    fn fill_samples_modulated(
+        &self,
+        state: &mut Self::State,
+        buffer: &mut [f32],
+        delta_t: f32,
+        freqs: &[f32],
+        amps: &[f32],
+    ) {
+        self.as_ref()
+            .fill_samples_modulated_dyn(state, buffer, delta_t, freqs, amps);
+    }
+}
+
+#[test]
+fn test_dyn_oscillator_matches_concrete() {
+    use super::saw::SawOscillator;
+
+    let boxed: Box<dyn DynOscillator> = Box::new(SawOscillator);
+    let mut dyn_buf = vec![0.0; 64];
+    let mut buf = vec![0.0; 64];
+    boxed.fill_samples(
+        &mut boxed.create_state(),
+        &mut dyn_buf,
+        1.0 / 44100.0,
+        440.0,
+        1.0,
+    );
+    SawOscillator.fill_samples(
+        &mut SawOscillator.create_state(),
+        &mut buf,
+        1.0 / 44100.0,
+        440.0,
+        1.0,
+    );
+    assert_eq!(dyn_buf, buf);
+}
@@ -0,0 +1,88 @@
+//! Fast approximations of `sin`, see [`SineApproximation`](super::sine::SineApproximation).
+//!
+//! Both functions take the phase in cycles rather than radians, which makes range reduction a
+//! single floor. The polynomial is branch-free so that loops over it can be auto-vectorized.
+
+use std::sync::OnceLock;
+
+use std::f32::consts::TAU;
+
+/// The number of phases evaluated together. There is no explicit SIMD; chunks of 8 give the
+/// compiler's auto-vectorizer a fixed trip count, which on x86-64 it turns into SSE code.
+pub(crate) const LANES: usize = 8;
+
+/// `floor` for phases below 2^22 in magnitude. Without SSE4.1, `f32::floor` is a library call
+/// that keeps loops from being vectorized; rounding by adding and subtracting 1.5 * 2^23 is not.
+#[inline]
+fn floor(t: f32) -> f32 {
+    const ROUND: f32 = 12582912.0;
+    let rounded = (t + ROUND) - ROUND;
+    if rounded > t {
+        rounded - 1.0
+    } else {
+        rounded
+    }
+}
+
+/// `sin(2 pi t)`, using an 11th order Taylor polynomial after folding `t` into `-1/4..1/4`.
+#[inline]
+pub(crate) fn sin_cycles_poly(t: f32) -> f32 {
+    // sin(2 pi t) = -sin(2 pi r) with r in -1/2..1/2
+    let r = t - floor(t) - 0.5;
+    // sin(2 pi r) = sin(2 pi (1/2 - r)), which folds r into -1/4..1/4
+    let folded = (0.25 - (r.abs() - 0.25).abs()).copysign(r);
+    let x = folded * TAU;
+    let x2 = x * x;
+    let p = 1.0 / 362880.0 - x2 * (1.0 / 39916800.0);
+    let p = -1.0 / 5040.0 + x2 * p;
+    let p = 1.0 / 120.0 + x2 * p;
+    let p = -1.0 / 6.0 + x2 * p;
+    let p = 1.0 + x2 * p;
+    -x * p
+}
+
+/// The number of intervals in the lookup table covering one cycle.
+const TABLE_SIZE: usize = 4096;
+
+/// The shared lookup table for [`sin_cycles_table`], with one extra point so that interpolation
+/// never has to wrap around.
+pub(crate) fn sine_table() -> &'static [f32; TABLE_SIZE + 1] {
+    static TABLE: OnceLock<[f32; TABLE_SIZE + 1]> = OnceLock::new();
+    TABLE.get_or_init(|| {
+        std::array::from_fn(|i| (std::f64::consts::TAU * i as f64 / TABLE_SIZE as f64).sin() as f32)
+    })
+}
+
+/// `sin(2 pi t)`, linearly interpolated from [`sine_table`]. Taking the table as an argument
+/// lets callers fetch it once per buffer.
+#[inline]
+pub(crate) fn sin_cycles_table(table: &[f32; TABLE_SIZE + 1], t: f32) -> f32 {
+    let idx = (t - floor(t)) * TABLE_SIZE as f32;
+    let i = (idx as i32 as usize).min(TABLE_SIZE - 1);
+    let frac = idx - i as f32;
+    table[i] + (table[i + 1] - table[i]) * frac
+}
+
+#[test]
+fn test_error_bounds() {
+    // Keep in sync with the bounds documented on `SineApproximation`.
+    let n = 100_000;
+    for i in 0..n {
+        let t = i as f64 / n as f64 * 3.0 - 1.0;
+        let exact = (std::f64::consts::TAU * t).sin();
+        let poly = sin_cycles_poly(t as f32) as f64;
+        let table = sin_cycles_table(sine_table(), t as f32) as f64;
+        assert!(
+            (poly - exact).abs() < 4e-7,
+            "poly at {}: {}",
+            t,
+            poly - exact
+        );
+        assert!(
+            (table - exact).abs() < 6e-7,
+            "table at {}: {}",
+            t,
+            table - exact
+        );
+    }
+}
@@ -0,0 +1,117 @@
+/// The routing between the operators of an [`FmOscillator`](super::FmOscillator).
+///
+/// Operators are evaluated from the last to the first, so an operator may only be modulated by
+/// operators with a higher index. Self-modulation is configured per operator with
+/// [`Operator::feedback`](super::Operator::feedback) instead.
+#[derive(Debug, Clone)]
+pub struct Algorithm {
+    /// For every operator, the indices of the operators modulating its phase.
+    modulators: Vec<Vec<usize>>,
+    /// The indices of the operators whose output is audible.
+    carriers: Vec<usize>,
+}
+
+impl Algorithm {
+    /// Create an algorithm from an explicit modulation graph.
+    ///
+    /// # Panics
+    ///
+    /// Panics if an operator is modulated by an operator with the same or lower index, or if any
+    /// index is out of range.
+    pub fn new(modulators: Vec<Vec<usize>>, carriers: Vec<usize>) -> Self {
+        let count = modulators.len();
+        for (op, mods) in modulators.iter().enumerate() {
+            for &m in mods {
+                assert!(
+                    m > op && m < count,
+                    "operator {} cannot be modulated by operator {}",
+                    op,
+                    m
+                );
+            }
+        }
+        for &c in &carriers {
+            assert!(c < count, "carrier {} is out of range", c);
+        }
+        Self {
+            modulators,
+            carriers,
+        }
+    }
+
+    /// A single chain where every operator modulates the previous one, and operator 0 is the
+    /// only carrier. This is DX7 algorithm 1 without its parallel branch.
+    ///
+    /// ```plaintext
+    /// [n-1] -> ... -> [1] -> [0] -> out
+    /// ```
+    pub fn stack(operators: usize) -> Self {
+        let modulators = (0..operators)
+            .map(|op| {
+                if op + 1 < operators {
+                    vec![op + 1]
+                } else {
+                    vec![]
+                }
+            })
+            .collect();
+        Self::new(modulators, vec![0])
+    }
+
+    /// All operators are carriers and nothing is modulated, i.e. plain additive synthesis.
+    pub fn parallel(operators: usize) -> Self {
+        Self::new(vec![vec![]; operators], (0..operators).collect())
+    }
+
+    /// Modulator-carrier pairs mixed together: operator `2k + 1` modulates operator `2k`. This
+    /// is the classic electric piano layout (DX7 algorithm 5 for six operators).
+    ///
+    /// ```plaintext
+    /// [1] -> [0] -+
+    /// [3] -> [2] -+-> out
+    /// ...        -+
+    /// ```
+    pub fn pairs(operators: usize) -> Self {
+        let modulators = (0..operators)
+            .map(|op| {
+                if op % 2 == 0 && op + 1 < operators {
+                    vec![op + 1]
+                } else {
+                    vec![]
+                }
+            })
+            .collect();
+        let carriers = (0..operators).step_by(2).collect();
+        Self::new(modulators, carriers)
+    }
+
+    /// Every other operator modulates operator 0 in parallel.
+    ///
+    /// ```plaintext
+    /// [1] -+
+    /// [2] -+-> [0] -> out
+    /// ...  -+
+    /// ```
+    pub fn branch(operators: usize) -> Self {
+        let mut modulators = vec![vec![]; operators];
+        if operators > 0 {
+            modulators[0] = (1..operators).collect();
+        }
+        Self::new(modulators, vec![0])
+    }
+
+    /// The number of operators this algorithm expects.
+    pub fn operator_count(&self) -> usize {
+        self.modulators.len()
+    }
+
+    /// The operators modulating the phase of operator `op`.
+    pub fn modulators_of(&self, op: usize) -> &[usize] {
+        &self.modulators[op]
+    }
+
+    /// The operators whose output is audible.
+    pub fn carriers(&self) -> &[usize] {
+        &self.carriers
+    }
+}
@@ -0,0 +1,212 @@
+//! Phase modulation ("FM") synthesis in the style of the Yamaha DX series.
+//!
+//! An [`FmOscillator`] is a set of sine [`Operator`]s wired together by an [`Algorithm`]. The
+//! output of a modulating operator is added to the phase of the operators it modulates, and the
+//! outputs of the carrier operators are summed to form the final sound.
+
+use crate::envelope::{
+    adsr::{AdsrEnvelope, AdsrEnvelopeState},
+    Envelope, NoteContext,
+};
+
+use super::{sine::SineOscillatorState, Oscillator};
+
+mod algorithm;
+
+pub use algorithm::Algorithm;
+
+/// A single sine operator.
+#[derive(Debug, Clone)]
+pub struct Operator {
+    /// The frequency of the operator relative to the note frequency.
+    pub ratio: f32,
+    /// The output level of the operator. For carriers this is an amplitude; for modulators it is
+    /// the modulation index, in radians.
+    pub level: f32,
+    /// How much of the operator's own output is fed back into its phase, in radians.
+    pub feedback: f32,
+    /// The envelope applied to the operator's output level. It is sampled with the time since the
+    /// note started; releasing the note is left to the synth's own envelope.
+    pub envelope: AdsrEnvelope,
+}
+
+impl Operator {
+    pub fn new(ratio: f32, level: f32) -> Self {
+        Self {
+            ratio,
+            level,
+            feedback: 0.0,
+            envelope: AdsrEnvelope::immediate(),
+        }
+    }
+
+    pub fn with_feedback(mut self, feedback: f32) -> Self {
+        self.feedback = feedback;
+        self
+    }
+
+    pub fn with_envelope(mut self, envelope: AdsrEnvelope) -> Self {
+        self.envelope = envelope;
+        self
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct FmOscillator {
+    operators: Vec<Operator>,
+    algorithm: Algorithm,
+}
+
+impl FmOscillator {
+    /// # Panics
+    ///
+    /// Panics if the number of operators does not match the algorithm.
+    pub fn new(operators: Vec<Operator>, algorithm: Algorithm) -> Self {
+        assert_eq!(
+            operators.len(),
+            algorithm.operator_count(),
+            "the algorithm expects {} operators",
+            algorithm.operator_count()
+        );
+        Self {
+            operators,
+            algorithm,
+        }
+    }
+
+    pub fn operators(&self) -> &[Operator] {
+        &self.operators
+    }
+
+    pub fn operators_mut(&mut self) -> &mut [Operator] {
+        &mut self.operators
+    }
+
+    pub fn algorithm(&self) -> &Algorithm {
+        &self.algorithm
+    }
+
+    /// Compute the next sample at unit amplitude.
+    fn next(&self, state: &mut FmOscillatorState, delta_t: f32, freq: f32) -> f32 {
+        let base_increment = 2.0 * std::f32::consts::PI * freq * delta_t;
+        let note = NoteContext::new(freq, 1.0, 1.0 / delta_t).with_time_held(state.time);
+        for (ix, op) in self.operators.iter().enumerate().rev() {
+            let mut modulation = self
+                .algorithm
+                .modulators_of(ix)
+                .iter()
+                .map(|m| state.outputs[*m])
+                .sum::<f32>();
+            // Averaging the last two outputs tames the noise that feedback otherwise causes.
+            let [a, b] = state.history[ix];
+            modulation += op.feedback * (a + b) * 0.5;
+
+            let phase = state.phases[ix].advance(base_increment * op.ratio);
+            let level = op.level * op.envelope.sample(&mut state.envelopes[ix], note);
+            let out = (phase + modulation).sin() * level;
+
+            state.outputs[ix] = out;
+            state.history[ix] = [out, a];
+        }
+        let out = self
+            .algorithm
+            .carriers()
+            .iter()
+            .map(|c| state.outputs[*c])
+            .sum::<f32>();
+        state.time += delta_t;
+        out
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct FmOscillatorState {
+    phases: Vec<SineOscillatorState>,
+    /// The last two outputs of every operator, used for feedback.
+    history: Vec<[f32; 2]>,
+    /// The output of every operator for the current sample.
+    outputs: Vec<f32>,
+    envelopes: Vec<AdsrEnvelopeState>,
+    /// The time since the note started, in seconds.
+    time: f32,
+}
+
+impl Oscillator for FmOscillator {
+    type State = FmOscillatorState;
+
+    fn create_state(&self) -> Self::State {
+        let count = self.operators.len();
+        FmOscillatorState {
+            phases: vec![SineOscillatorState::default(); count],
+            history: vec![[0.0; 2]; count],
+            outputs: vec![0.0; count],
+            envelopes: self
+                .operators
+                .iter()
+                .map(|op| op.envelope.create_state())
+                .collect(),
+            time: 0.0,
+        }
+    }
+
+    fn create_state_at(&self, phase: f32) -> Self::State {
+        let mut state = self.create_state();
+        for (op_state, op) in state.phases.iter_mut().zip(&self.operators) {
+            *op_state = SineOscillatorState::at(phase * op.ratio);
+        }
+        state
+    }
+
+    /// Only the operator phases are reset; the operator envelopes and feedback carry on.
+    fn reset_phase(&self, state: &mut Self::State, phase: f32) {
+        for (op_state, op) in state.phases.iter_mut().zip(&self.operators) {
+            *op_state = SineOscillatorState::at(phase * op.ratio);
+        }
+    }
+
+    fn fill_samples(
+        &self,
+        state: &mut Self::State,
+        buffer: &mut [f32],
+        delta_t: f32,
+        freq: f32,
+        amp: f32,
+    ) {
+        for sample in buffer.iter_mut() {
+            *sample += self.next(state, delta_t, freq) * amp;
+        }
+    }
+
+    fn fill_samples_modulated(
+        &self,
+        state: &mut Self::State,
+        buffer: &mut [f32],
+        delta_t: f32,
+        freqs: &[f32],
+        amps: &[f32],
+    ) {
+        for ((sample, freq), amp) in buffer.iter_mut().zip(freqs).zip(amps) {
+            *sample += self.next(state, delta_t, *freq) * amp;
+        }
+    }
+}
+
+#[test]
+fn test_single_operator_is_sine() {
+    use super::sine::SineOscillator;
+
+    let fm = FmOscillator::new(vec![Operator::new(1.0, 1.0)], Algorithm::stack(1));
+    let mut fm_state = fm.create_state();
+    let mut sine_state = SineOscillator::default().create_state();
+    let mut fm_buf = vec![0.0; 128];
+    let mut sine_buf = vec![0.0; 128];
+    fm.fill_samples(&mut fm_state, &mut fm_buf, 1.0 / 44100.0, 440.0, 0.5);
+    SineOscillator::default().fill_samples(
+        &mut sine_state,
+        &mut sine_buf,
+        1.0 / 44100.0,
+        440.0,
+        0.5,
+    );
+    assert_eq!(fm_buf, sine_buf);
+}
@@ -0,0 +1,363 @@
+//! A vowel synthesizer: a glottal pulse train shaped by formant resonances.
+//!
+//! The source is a differentiated Rosenberg glottal pulse at the note frequency. It is filtered
+//! by five parallel two-pole resonators whose frequencies are fixed in Hz, so the vowel stays
+//! recognizable regardless of the pitch.
+
+use super::Oscillator;
+
+/// The five vowels, in the order they appear along the vowel position.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Vowel {
+    A,
+    E,
+    I,
+    O,
+    U,
+}
+
+impl Vowel {
+    /// The vowel position of this vowel, see the `vowel` field of [`FormantOscillator`].
+    pub fn position(self) -> f32 {
+        self as usize as f32
+    }
+}
+
+const FORMANTS: usize = 5;
+
+/// A vowel's formants: `(frequency in Hz, bandwidth in Hz, gain in dB)`.
+type FormantSet = [(f32, f32, f32); FORMANTS];
+
+/// Formants of a bass voice, in the order of [`Vowel`].
+const VOWELS: [FormantSet; 5] = [
+    // a
+    [
+        (800.0, 80.0, 0.0),
+        (1150.0, 90.0, -6.0),
+        (2900.0, 120.0, -32.0),
+        (3900.0, 130.0, -20.0),
+        (4950.0, 140.0, -50.0),
+    ],
+    // e
+    [
+        (400.0, 60.0, 0.0),
+        (1600.0, 80.0, -24.0),
+        (2700.0, 120.0, -30.0),
+        (3300.0, 150.0, -35.0),
+        (4950.0, 200.0, -60.0),
+    ],
+    // i
+    [
+        (250.0, 60.0, 0.0),
+        (1750.0, 90.0, -30.0),
+        (2600.0, 100.0, -16.0),
+        (3050.0, 120.0, -22.0),
+        (3340.0, 120.0, -28.0),
+    ],
+    // o
+    [
+        (400.0, 40.0, 0.0),
+        (750.0, 80.0, -11.0),
+        (2400.0, 100.0, -21.0),
+        (2600.0, 120.0, -20.0),
+        (2900.0, 120.0, -40.0),
+    ],
+    // u
+    [
+        (350.0, 40.0, 0.0),
+        (600.0, 80.0, -20.0),
+        (2400.0, 100.0, -32.0),
+        (2675.0, 120.0, -28.0),
+        (2950.0, 120.0, -36.0),
+    ],
+];
+
+/// The fraction of the glottal cycle during which the glottis opens.
+const OPENING: f32 = 0.4;
+/// The fraction of the glottal cycle during which the glottis closes.
+const CLOSING: f32 = 0.16;
+/// Brings the output to roughly unit peak amplitude.
+const OUTPUT_GAIN: f32 = 0.4;
+
+/// Sings a vowel. See the [module documentation](self).
+#[derive(Debug, Clone)]
+pub struct FormantOscillator {
+    /// The vowel position between 0 and 4: 0 is a, 1 is e, 2 is i, 3 is o and 4 is u. Positions
+    /// in between interpolate the neighbouring vowels. Changes are smoothed over one buffer.
+    ///
+    /// The vowel belongs to the oscillator, so changing it through
+    /// [`Synth::osc_mut`](crate::Synth::osc_mut) changes it for every playing note. Use a
+    /// separate synth for every part that needs its own vowel.
+    pub vowel: f32,
+}
+
+impl FormantOscillator {
+    pub fn new(vowel: f32) -> Self {
+        Self { vowel }
+    }
+
+    pub fn vowel(vowel: Vowel) -> Self {
+        Self::new(vowel.position())
+    }
+}
+
+impl Default for FormantOscillator {
+    fn default() -> Self {
+        Self::vowel(Vowel::A)
+    }
+}
+
+/// Interpolate the formants at a vowel position.
+fn formants_at(position: f32) -> FormantSet {
+    let position = position.clamp(0.0, (VOWELS.len() - 1) as f32);
+    let first = (position as usize).min(VOWELS.len() - 2);
+    let t = position - first as f32;
+    let mut out = VOWELS[first];
+    for (o, b) in out.iter_mut().zip(&VOWELS[first + 1]) {
+        o.0 += (b.0 - o.0) * t;
+        o.1 += (b.1 - o.1) * t;
+        o.2 += (b.2 - o.2) * t;
+    }
+    out
+}
+
+/// The coefficients of a two-pole resonator: `y = gain * x + a1 * y1 + a2 * y2`.
+#[derive(Debug, Clone, Copy, Default)]
+struct Resonator {
+    gain: f32,
+    a1: f32,
+    a2: f32,
+}
+
+impl Resonator {
+    /// Interpolate the coefficients of two resonators. Two-pole filters are stable for
+    /// coefficients within a triangle, so interpolating between stable ones stays stable.
+    fn lerp(self, other: Self, t: f32) -> Self {
+        Self {
+            gain: self.gain + (other.gain - self.gain) * t,
+            a1: self.a1 + (other.a1 - self.a1) * t,
+            a2: self.a2 + (other.a2 - self.a2) * t,
+        }
+    }
+
+    fn new(freq: f32, bandwidth: f32, gain_db: f32, delta_t: f32) -> Self {
+        let nyquist = 0.5 / delta_t;
+        if freq >= nyquist {
+            return Self::default();
+        }
+        let r = (-std::f32::consts::PI * bandwidth * delta_t).exp();
+        let theta = 2.0 * std::f32::consts::PI * freq * delta_t;
+        // Normalize the gain at the resonant frequency to 1.
+        let norm = (1.0 - r) * (1.0 - 2.0 * r * (2.0 * theta).cos() + r * r).sqrt();
+        Self {
+            gain: norm * 10.0_f32.powf(gain_db / 20.0),
+            a1: 2.0 * r * theta.cos(),
+            a2: -r * r,
+        }
+    }
+}
+
+fn resonators_at(position: f32, delta_t: f32) -> [Resonator; FORMANTS] {
+    formants_at(position).map(|(freq, bw, gain)| Resonator::new(freq, bw, gain, delta_t))
+}
+
+/// The Rosenberg glottal flow at a phase between 0 and 1.
+fn glottal_flow(phase: f32) -> f32 {
+    if phase < OPENING {
+        0.5 * (1.0 - (std::f32::consts::PI * phase / OPENING).cos())
+    } else if phase < OPENING + CLOSING {
+        (std::f32::consts::FRAC_PI_2 * (phase - OPENING) / CLOSING).cos()
+    } else {
+        0.0
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct FormantOscillatorState {
+    /// The phase of the glottal pulse, between 0 and 1.
+    phase: f32,
+    /// The previous glottal flow, for differentiation.
+    flow: f32,
+    /// The last two outputs of every resonator.
+    history: [[f32; 2]; FORMANTS],
+    /// The vowel position at the end of the last buffer, to smooth changes.
+    vowel: f32,
+}
+
+impl Oscillator for FormantOscillator {
+    type State = FormantOscillatorState;
+
+    fn create_state(&self) -> Self::State {
+        FormantOscillatorState {
+            phase: 0.0,
+            flow: 0.0,
+            history: [[0.0; 2]; FORMANTS],
+            vowel: self.vowel,
+        }
+    }
+
+    fn create_state_at(&self, phase: f32) -> Self::State {
+        let phase = phase.rem_euclid(1.0);
+        FormantOscillatorState {
+            phase,
+            flow: glottal_flow(phase),
+            ..self.create_state()
+        }
+    }
+
+    fn fill_samples(
+        &self,
+        state: &mut Self::State,
+        buffer: &mut [f32],
+        delta_t: f32,
+        freq: f32,
+        amp: f32,
+    ) {
+        let increment = freq * delta_t;
+        self.render(state, buffer, delta_t, |_| (increment, amp));
+    }
+
+    fn fill_samples_modulated(
+        &self,
+        state: &mut Self::State,
+        buffer: &mut [f32],
+        delta_t: f32,
+        freqs: &[f32],
+        amps: &[f32],
+    ) {
+        self.render(state, buffer, delta_t, |i| (freqs[i] * delta_t, amps[i]));
+    }
+}
+
+impl FormantOscillator {
+    /// Render samples, with `step(i)` giving the phase increment and the amplitude of sample
+    /// `i`. Vowel changes are interpolated across the whole buffer.
+    fn render(
+        &self,
+        state: &mut FormantOscillatorState,
+        buffer: &mut [f32],
+        delta_t: f32,
+        mut step: impl FnMut(usize) -> (f32, f32),
+    ) {
+        let gliding = state.vowel != self.vowel;
+        let target = resonators_at(self.vowel, delta_t);
+        // While gliding, the coefficients are interpolated rather than recomputed every sample.
+        let start = if gliding {
+            resonators_at(state.vowel, delta_t)
+        } else {
+            target
+        };
+        let mut resonators = target;
+        let len = buffer.len() as f32;
+
+        for (i, sample) in buffer.iter_mut().enumerate() {
+            if gliding {
+                let t = (i + 1) as f32 / len;
+                for ((res, start), target) in resonators.iter_mut().zip(&start).zip(&target) {
+                    *res = start.lerp(*target, t);
+                }
+            }
+
+            let (increment, amp) = step(i);
+            let flow = glottal_flow(state.phase);
+            // Differentiating the flow models the radiation at the lips. Dividing by the phase
+            // increment keeps the pulse shape, and with it the level, independent of the pitch.
+            let source = (flow - state.flow) / increment.max(1e-6);
+            state.flow = flow;
+            state.phase += increment;
+            state.phase %= 1.0;
+
+            let mut out = 0.0;
+            for (res, [y1, y2]) in resonators.iter().zip(state.history.iter_mut()) {
+                let y = res.gain * source + res.a1 * *y1 + res.a2 * *y2;
+                *y2 = *y1;
+                *y1 = y;
+                out += y;
+            }
+            *sample += out * OUTPUT_GAIN * amp;
+        }
+        state.vowel = self.vowel;
+    }
+}
+
+#[test]
+fn test_spectral_peak_follows_first_formant() {
+    // The first formant of a is at 800 Hz, which is the 8th harmonic of 100 Hz and the 5th
+    // harmonic of 160 Hz. At both pitches, that harmonic should be the loudest above 400 Hz;
+    // below, the glottal source itself is loud enough to compete.
+    let osc = FormantOscillator::vowel(Vowel::A);
+    let sample_rate = 44100.0;
+    for (freq, loudest) in [(100.0, 8), (160.0, 5)] {
+        let mut buf = vec![0.0; 44100];
+        osc.fill_samples(
+            &mut osc.create_state(),
+            &mut buf,
+            1.0 / sample_rate,
+            freq,
+            1.0,
+        );
+        // Skip the attack of the resonators, then correlate with every harmonic.
+        let steady = &buf[4410..];
+        let magnitude = |harmonic: usize| {
+            let w = std::f32::consts::TAU * freq * harmonic as f32 / sample_rate;
+            let (re, im) = steady
+                .iter()
+                .enumerate()
+                .fold((0.0, 0.0), |(re, im), (n, x)| {
+                    let (sin, cos) = (w * n as f32).sin_cos();
+                    (re + x * cos, im + x * sin)
+                });
+            f32::hypot(re, im)
+        };
+        let harmonics = (400.0 / freq) as usize + 1..(2000.0 / freq) as usize;
+        let peak = harmonics
+            .max_by(|a, b| magnitude(*a).total_cmp(&magnitude(*b)))
+            .unwrap();
+        assert_eq!(peak, loudest, "at {} Hz", freq);
+    }
+}
+
+#[test]
+fn test_vowel_glide_is_smoothed_with_varying_frequency() {
+    // With vibrato, the vowel should still glide over the whole buffer instead of jumping to
+    // its target on the first sample.
+    let delta_t = 1.0 / 44100.0;
+    let vibrato = |offset: usize, len: usize| -> Vec<f32> {
+        (offset..offset + len)
+            .map(|i| 150.0 + 5.0 * (i as f32 * 0.01).sin())
+            .collect()
+    };
+    let a = FormantOscillator::vowel(Vowel::A);
+    let i = FormantOscillator::vowel(Vowel::I);
+    let mut state = a.create_state();
+    let mut warmup = vec![0.0; 2048];
+    a.fill_samples_modulated(
+        &mut state,
+        &mut warmup,
+        delta_t,
+        &vibrato(0, 2048),
+        &[1.0; 2048],
+    );
+
+    let (mut steady, mut glide) = (vec![0.0; 512], vec![0.0; 512]);
+    let freqs = vibrato(2048, 512);
+    a.fill_samples_modulated(
+        &mut state.clone(),
+        &mut steady,
+        delta_t,
+        &freqs,
+        &[1.0; 512],
+    );
+    let mut glide_state = state.clone();
+    i.fill_samples_modulated(&mut glide_state, &mut glide, delta_t, &freqs, &[1.0; 512]);
+    assert_eq!(glide_state.vowel, Vowel::I.position());
+
+    let difference = |range: std::ops::Range<usize>| {
+        range
+            .map(|n| (steady[n] - glide[n]).abs())
+            .fold(0.0, f32::max)
+    };
+    let peak = steady.iter().fold(0.0, |peak: f32, x| peak.max(x.abs()));
+    assert!(difference(0..32) < 0.02 * peak);
+    assert!(difference(256..512) > 0.1 * peak);
+}
@@ -0,0 +1,222 @@
+//! Granular synthesis: a cloud of short, overlapping, windowed grains read from a source buffer.
+
+use rand::Rng;
+use rand_chacha::ChaCha8Rng;
+
+use super::{noise::NoteRngs, Oscillator};
+
+/// The maximum number of grains playing at once in a single note.
+const MAX_GRAINS: usize = 128;
+
+#[derive(Debug, Clone)]
+pub struct GranularOscillator {
+    /// The mono source audio.
+    source: Vec<f32>,
+    /// The sample rate of `source`, in Hz.
+    sample_rate: f32,
+    /// The pitch of `source`, in Hz. Grains are repitched by the note frequency relative to it.
+    root_freq: f32,
+    /// The length of every grain, in seconds.
+    pub grain_size: f32,
+    /// The number of grains started per second.
+    pub density: f32,
+    /// Where grains start reading in the source, between 0 (start) and 1 (end).
+    pub position: f32,
+    /// How far the start of every grain is randomly moved away from `position`, as a fraction of
+    /// the source length.
+    pub position_jitter: f32,
+    /// How far the pitch of every grain is randomly detuned, in semitones.
+    pub pitch_jitter: f32,
+    /// The seed of the grain scheduler's random number generator. As with
+    /// [`NoiseOscillator::seed`](super::noise::NoiseOscillator::seed), every note gets its own
+    /// stream, so that held notes are separate clouds.
+    pub seed: Option<u64>,
+    rngs: NoteRngs,
+}
+
+impl GranularOscillator {
+    /// Create a granular oscillator from source audio recorded at `sample_rate`, whose pitch is
+    /// `root_freq`.
+    pub fn new(source: Vec<f32>, sample_rate: f32, root_freq: f32) -> Self {
+        Self {
+            source,
+            sample_rate,
+            root_freq,
+            grain_size: 0.05,
+            density: 40.0,
+            position: 0.0,
+            position_jitter: 0.0,
+            pitch_jitter: 0.0,
+            seed: None,
+            rngs: NoteRngs::default(),
+        }
+    }
+
+    /// Create a granular oscillator by rendering `duration` seconds of another oscillator at
+    /// `freq`.
+    pub fn from_oscillator<O: Oscillator>(
+        osc: &O,
+        sample_rate: f32,
+        freq: f32,
+        duration: f32,
+    ) -> Self {
+        let mut state = osc.create_state();
+        let mut source = vec![0.0; (duration * sample_rate) as usize];
+        osc.fill_samples(&mut state, &mut source, 1.0 / sample_rate, freq, 1.0);
+        Self::new(source, sample_rate, freq)
+    }
+
+    pub fn with_grain_size(mut self, grain_size: f32) -> Self {
+        self.grain_size = grain_size;
+        self
+    }
+
+    pub fn with_density(mut self, density: f32) -> Self {
+        self.density = density;
+        self
+    }
+
+    pub fn with_position(mut self, position: f32, jitter: f32) -> Self {
+        self.position = position;
+        self.position_jitter = jitter;
+        self
+    }
+
+    pub fn with_pitch_jitter(mut self, pitch_jitter: f32) -> Self {
+        self.pitch_jitter = pitch_jitter;
+        self
+    }
+
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    fn read(&self, pos: f64) -> f32 {
+        if pos < 0.0 {
+            return 0.0;
+        }
+        let ix = pos as usize;
+        let frac = (pos - ix as f64) as f32;
+        let a = self.source.get(ix).copied().unwrap_or(0.0);
+        let b = self.source.get(ix + 1).copied().unwrap_or(0.0);
+        a + (b - a) * frac
+    }
+
+    fn spawn(&self, rng: &mut ChaCha8Rng, delta_t: f32, freq: f32) -> Grain {
+        let len = self.source.len() as f32;
+        let jitter = if self.position_jitter > 0.0 {
+            rng.gen_range(-1.0..1.0) * self.position_jitter
+        } else {
+            0.0
+        };
+        let start = ((self.position + jitter).clamp(0.0, 1.0) * len) as f64;
+        let detune = if self.pitch_jitter > 0.0 {
+            rng.gen_range(-1.0..1.0) * self.pitch_jitter
+        } else {
+            0.0
+        };
+        let pitch = freq / self.root_freq * 2.0_f32.powf(detune / 12.0);
+        Grain {
+            pos: start,
+            step: (pitch * self.sample_rate * delta_t) as f64,
+            age: 0,
+            len: ((self.grain_size / delta_t) as usize).max(1),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Grain {
+    /// The read position in the source, in samples.
+    pos: f64,
+    /// The number of source samples advanced per output sample.
+    step: f64,
+    /// The number of samples this grain has played.
+    age: usize,
+    /// The total length of this grain, in output samples.
+    len: usize,
+}
+
+impl Grain {
+    /// A Hann window over the grain's lifetime.
+    fn window(&self) -> f32 {
+        let t = self.age as f32 / self.len as f32;
+        0.5 - 0.5 * (2.0 * std::f32::consts::PI * t).cos()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct GranularOscillatorState {
+    grains: Vec<Grain>,
+    /// The number of samples until the next grain starts.
+    next_grain: f32,
+    rng: ChaCha8Rng,
+}
+
+impl Oscillator for GranularOscillator {
+    type State = GranularOscillatorState;
+
+    fn create_state(&self) -> Self::State {
+        GranularOscillatorState {
+            grains: Vec::with_capacity(MAX_GRAINS),
+            next_grain: 0.0,
+            rng: self.rngs.next(self.seed),
+        }
+    }
+
+    fn fill_samples(
+        &self,
+        state: &mut Self::State,
+        buffer: &mut [f32],
+        delta_t: f32,
+        freq: f32,
+        amp: f32,
+    ) {
+        if self.source.is_empty() || self.density <= 0.0 {
+            return;
+        }
+        let interval = 1.0 / (self.density * delta_t);
+        // Grains are uncorrelated, so their powers add up.
+        let overlap = self.density * self.grain_size * 0.5;
+        let gain = amp / overlap.max(1.0).sqrt();
+
+        for sample in buffer.iter_mut() {
+            while state.next_grain <= 0.0 {
+                if state.grains.len() < MAX_GRAINS {
+                    let grain = self.spawn(&mut state.rng, delta_t, freq);
+                    state.grains.push(grain);
+                }
+                state.next_grain += interval;
+            }
+            state.next_grain -= 1.0;
+
+            let mut out = 0.0;
+            for grain in state.grains.iter_mut() {
+                out += self.read(grain.pos) * grain.window();
+                grain.pos += grain.step;
+                grain.age += 1;
+            }
+            state.grains.retain(|g| g.age < g.len);
+            *sample += out * gain;
+        }
+    }
+}
+
+#[test]
+fn test_seeded_notes_are_separate_clouds() {
+    use super::sine::SineOscillator;
+
+    let osc = GranularOscillator::from_oscillator(&SineOscillator::default(), 44100.0, 220.0, 0.5)
+        .with_position(0.5, 0.5)
+        .with_seed(7);
+    let render = |osc: &GranularOscillator| {
+        let mut buf = vec![0.0; 4410];
+        osc.fill_samples(&mut osc.create_state(), &mut buf, 1.0 / 44100.0, 220.0, 1.0);
+        buf
+    };
+    let copy = osc.clone();
+    let first = render(&osc);
+    assert_ne!(render(&osc), first);
+    assert_eq!(render(&copy), first);
+}
@@ -1,29 +1,124 @@
 use super::{
-    sine::{SineOscillator, SineOscillatorState},
+    sine::{SineApproximation, SineOscillator, SineOscillatorState},
     Oscillator,
 };
 
+/// A single sine partial of a [`HarmonicOscillator`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Partial {
+    /// The frequency of the partial relative to the note frequency. Integer ratios are harmonic.
+    pub ratio: f32,
+    /// The amplitude of the partial, before the rolloff is applied.
+    pub amplitude: f32,
+    /// The starting phase of the partial, between 0 and 1.
+    pub phase: f32,
+}
+
+impl Partial {
+    pub fn new(ratio: f32, amplitude: f32, phase: f32) -> Self {
+        Self {
+            ratio,
+            amplitude,
+            phase,
+        }
+    }
+}
+
+/// How partial amplitudes are scaled according to their frequency ratio.
+#[derive(Debug, Clone, Copy, Default)]
+pub enum Rolloff {
+    /// Use the amplitudes as given.
+    #[default]
+    None,
+    /// Divide every amplitude by its frequency ratio. This is what [`HarmonicOscillator::new`]
+    /// does.
+    Inverse,
+    /// Divide every amplitude by its frequency ratio raised to the given power.
+    Power(f32),
+}
+
+impl Rolloff {
+    fn scale(self, ratio: f32) -> f32 {
+        match self {
+            Rolloff::None => 1.0,
+            Rolloff::Inverse => 1.0 / ratio,
+            Rolloff::Power(p) => 1.0 / ratio.powf(p),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Default)]
 struct TableEntry {
     osc: SineOscillator,
+    ratio: f32,
+    /// The amplitude with the rolloff already applied.
     amplitude: f32,
+    phase: f32,
 }
 
 #[derive(Debug, Clone, Default)]
 pub struct HarmonicOscillator {
     table: Vec<TableEntry>,
+    /// The fraction of the Nyquist frequency over which partials fade out before being culled.
+    /// With 0, partials are dropped as soon as they reach the Nyquist frequency; with 0.1, they
+    /// fade out linearly from 90% of it.
+    pub nyquist_fade: f32,
 }
 
 impl HarmonicOscillator {
+    /// Create an oscillator from the amplitudes of integer harmonics, starting at the
+    /// fundamental. Every amplitude is divided by its harmonic number.
     pub fn new(amps: &[f32]) -> Self {
-        let mut table = Vec::with_capacity(amps.len());
-        for amp in amps {
+        let partials = amps
+            .iter()
+            .enumerate()
+            .map(|(ix, amp)| Partial::new((ix + 1) as f32, *amp, 0.0))
+            .collect::<Vec<_>>();
+        Self::from_partials(&partials, Rolloff::Inverse)
+    }
+
+    /// Create an oscillator from arbitrary, possibly inharmonic, partials.
+    pub fn from_partials(partials: &[Partial], rolloff: Rolloff) -> Self {
+        let mut table = Vec::with_capacity(partials.len());
+        for partial in partials {
             table.push(TableEntry {
-                osc: SineOscillator,
-                amplitude: *amp,
+                osc: SineOscillator::default(),
+                ratio: partial.ratio,
+                amplitude: partial.amplitude * rolloff.scale(partial.ratio),
+                phase: partial.phase,
             });
         }
-        Self { table }
+        Self {
+            table,
+            nyquist_fade: 0.0,
+        }
+    }
+
+    /// Select how the partials compute `sin`. See [`SineApproximation`].
+    pub fn with_sine_approximation(mut self, approximation: SineApproximation) -> Self {
+        for entry in &mut self.table {
+            entry.osc.approximation = approximation;
+        }
+        self
+    }
+
+    pub fn with_nyquist_fade(mut self, nyquist_fade: f32) -> Self {
+        self.nyquist_fade = nyquist_fade;
+        self
+    }
+
+    /// The gain of a partial at `freq`, which is 0 for partials that would alias.
+    fn nyquist_gain(&self, freq: f32, nyquist: f32) -> f32 {
+        let freq = freq.abs();
+        if freq >= nyquist {
+            return 0.0;
+        }
+        let fade_start = nyquist * (1.0 - self.nyquist_fade.clamp(0.0, 1.0));
+        if freq <= fade_start {
+            1.0
+        } else {
+            (nyquist - freq) / (nyquist - fade_start)
+        }
     }
 }
 
@@ -36,11 +131,22 @@ impl Oscillator for HarmonicOscillator {
     type State = HarmonicOscillatorState;
 
     fn create_state(&self) -> Self::State {
-        let mut state = Vec::with_capacity(self.table.len());
-        for _ in &self.table {
-            state.push(SineOscillatorState::default());
+        self.create_state_at(0.0)
+    }
+
+    fn create_state_at(&self, phase: f32) -> Self::State {
+        let states = self
+            .table
+            .iter()
+            .map(|entry| SineOscillatorState::at(entry.phase + phase * entry.ratio))
+            .collect();
+        HarmonicOscillatorState { states }
+    }
+
+    fn reset_phase(&self, state: &mut Self::State, phase: f32) {
+        for (entry, state) in self.table.iter().zip(state.states.iter_mut()) {
+            *state = SineOscillatorState::at(entry.phase + phase * entry.ratio);
         }
-        HarmonicOscillatorState { states: state }
     }
 
     fn fill_samples(
@@ -51,14 +157,176 @@ impl Oscillator for HarmonicOscillator {
         freq: f32,
         amp: f32,
     ) {
-        buffer.fill(0.0);
-        for (ix, (entry, state)) in self.table.iter().zip(state.states.iter_mut()).enumerate() {
-            let multiplier = ix + 1;
-            let entry_freq = freq * multiplier as f32;
-            let entry_amp = entry.amplitude * amp / multiplier as f32;
+        let nyquist = 0.5 / delta_t;
+        for (entry, state) in self.table.iter().zip(state.states.iter_mut()) {
+            let entry_freq = freq * entry.ratio;
+            let gain = self.nyquist_gain(entry_freq, nyquist);
+            if gain == 0.0 {
+                // Keep the culled partial in phase in case it comes back below Nyquist.
+                state.advance(
+                    2.0 * std::f32::consts::PI * entry_freq * delta_t * buffer.len() as f32,
+                );
+                continue;
+            }
+            let entry_amp = entry.amplitude * amp * gain;
             entry
                 .osc
                 .fill_samples(state, buffer, delta_t, entry_freq, entry_amp);
         }
     }
+
+    fn fill_samples_modulated(
+        &self,
+        state: &mut Self::State,
+        buffer: &mut [f32],
+        delta_t: f32,
+        freqs: &[f32],
+        amps: &[f32],
+    ) {
+        let nyquist = 0.5 / delta_t;
+        let mut entry_freqs = vec![0.0; buffer.len()];
+        let mut entry_amps = vec![0.0; buffer.len()];
+        for (entry, state) in self.table.iter().zip(state.states.iter_mut()) {
+            let mut culled = true;
+            for (ix, (freq, amp)) in freqs.iter().zip(amps).take(buffer.len()).enumerate() {
+                entry_freqs[ix] = freq * entry.ratio;
+                let gain = self.nyquist_gain(entry_freqs[ix], nyquist);
+                entry_amps[ix] = entry.amplitude * amp * gain;
+                culled &= gain == 0.0;
+            }
+            if culled {
+                // Keep the culled partial in phase in case it comes back below Nyquist.
+                let cycles = entry_freqs.iter().sum::<f32>() * delta_t;
+                state.advance(2.0 * std::f32::consts::PI * cycles);
+                continue;
+            }
+            entry
+                .osc
+                .fill_samples_modulated(state, buffer, delta_t, &entry_freqs, &entry_amps);
+        }
+    }
+}
+
+#[test]
+fn test_partials_above_nyquist_are_culled() {
+    // At 8 kHz and 44.1 kHz only the first two harmonics are below Nyquist.
+    let osc = HarmonicOscillator::from_partials(
+        &[
+            Partial::new(1.0, 1.0, 0.0),
+            Partial::new(2.0, 0.5, 0.0),
+            Partial::new(3.0, 0.25, 0.0),
+        ],
+        Rolloff::None,
+    );
+    let reference = HarmonicOscillator::from_partials(
+        &[Partial::new(1.0, 1.0, 0.0), Partial::new(2.0, 0.5, 0.0)],
+        Rolloff::None,
+    );
+    let mut buf = vec![0.0; 64];
+    let mut reference_buf = vec![0.0; 64];
+    osc.fill_samples(
+        &mut osc.create_state(),
+        &mut buf,
+        1.0 / 44100.0,
+        8000.0,
+        1.0,
+    );
+    reference.fill_samples(
+        &mut reference.create_state(),
+        &mut reference_buf,
+        1.0 / 44100.0,
+        8000.0,
+        1.0,
+    );
+    assert_eq!(buf, reference_buf);
+}
+
+/// The amplitude of the component at `freq` in `samples`, rendered at 44.1 kHz. Exact for whole
+/// numbers of cycles.
+#[cfg(test)]
+fn magnitude_at(samples: &[f32], freq: f32) -> f32 {
+    let w = std::f32::consts::TAU * freq / 44100.0;
+    let (re, im) = samples
+        .iter()
+        .enumerate()
+        .fold((0.0, 0.0), |(re, im), (n, x)| {
+            let (sin, cos) = (w * n as f32).sin_cos();
+            (re + x * cos, im + x * sin)
+        });
+    2.0 * f32::hypot(re, im) / samples.len() as f32
+}
+
+#[cfg(test)]
+fn render(osc: &HarmonicOscillator, len: usize, freq: f32) -> Vec<f32> {
+    let mut buf = vec![0.0; len];
+    osc.fill_samples(&mut osc.create_state(), &mut buf, 1.0 / 44100.0, freq, 1.0);
+    buf
+}
+
+#[test]
+fn test_inharmonic_partials() {
+    let osc = HarmonicOscillator::from_partials(
+        &[Partial::new(1.0, 1.0, 0.0), Partial::new(2.76, 0.5, 0.0)],
+        Rolloff::None,
+    );
+    let out = render(&osc, 44100, 100.0);
+    assert!((magnitude_at(&out, 100.0) - 1.0).abs() < 1e-2);
+    assert!((magnitude_at(&out, 276.0) - 0.5).abs() < 1e-2);
+    for harmonic in [200.0, 300.0] {
+        assert!(magnitude_at(&out, harmonic) < 1e-2);
+    }
+}
+
+#[test]
+fn test_partial_phase() {
+    // A quarter cycle in, a sine is at its peak.
+    let osc = HarmonicOscillator::from_partials(
+        &[Partial::new(1.0, 1.0, 0.25), Partial::new(2.0, 0.5, 0.0)],
+        Rolloff::None,
+    );
+    assert!((render(&osc, 1, 100.0)[0] - 1.0).abs() < 1e-6);
+    // Starting a quarter cycle later moves the second partial by half of its cycle.
+    let mut buf = [0.0];
+    osc.fill_samples(
+        &mut osc.create_state_at(0.25),
+        &mut buf,
+        1.0 / 44100.0,
+        100.0,
+        1.0,
+    );
+    assert!(buf[0].abs() < 1e-6, "{}", buf[0]);
+}
+
+#[test]
+fn test_rolloff() {
+    for (rolloff, expected) in [
+        (Rolloff::None, 1.0),
+        (Rolloff::Inverse, 1.0 / 3.0),
+        (Rolloff::Power(2.0), 1.0 / 9.0),
+        (Rolloff::Power(0.5), 1.0 / 3.0_f32.sqrt()),
+    ] {
+        let osc = HarmonicOscillator::from_partials(&[Partial::new(3.0, 1.0, 0.0)], rolloff);
+        let out = render(&osc, 44100, 100.0);
+        assert!((magnitude_at(&out, 300.0) - expected).abs() < 1e-3);
+    }
+}
+
+#[test]
+fn test_modulated_fill_culls_partials() {
+    // The third harmonic is culled at 8 kHz but not at 4 kHz, and must come back in phase.
+    let osc = HarmonicOscillator::new(&[1.0, 1.0, 1.0]);
+    let delta_t = 1.0 / 44100.0;
+    let mut scalar = vec![0.0; 200];
+    let mut state = osc.create_state();
+    let (first, second) = scalar.split_at_mut(100);
+    osc.fill_samples(&mut state, first, delta_t, 8000.0, 1.0);
+    osc.fill_samples(&mut state, second, delta_t, 4000.0, 1.0);
+    let mut modulated = vec![0.0; 200];
+    let mut state = osc.create_state();
+    let (first, second) = modulated.split_at_mut(100);
+    osc.fill_samples_modulated(&mut state, first, delta_t, &[8000.0; 100], &[1.0; 100]);
+    osc.fill_samples_modulated(&mut state, second, delta_t, &[4000.0; 100], &[1.0; 100]);
+    for (a, b) in scalar.iter().zip(&modulated) {
+        assert!((a - b).abs() < 1e-3, "{} != {}", a, b);
+    }
 }
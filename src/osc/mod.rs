@@ -1,9 +1,25 @@
+mod blep;
+pub mod chiptune;
+pub mod combinator;
+pub mod dynamic;
+mod fast_sin;
+pub mod fm;
+pub mod formant;
+pub mod granular;
 pub mod harmonic;
+pub mod modal;
 pub mod noise;
+pub mod phase_distortion;
+pub mod pluck;
+pub mod resynthesis;
+pub mod sampler;
 pub mod saw;
+pub mod shaper;
 pub mod sine;
 pub mod square;
 pub mod triangle;
+pub mod unison;
+pub mod wavetable;
 
 pub trait Oscillator {
     /// This type should store the state of the oscillator.
@@ -12,6 +28,25 @@ pub trait Oscillator {
     /// Create a new state for the oscillator.
     fn create_state(&self) -> Self::State;
 
+    /// Create a new state starting at the given `phase` of the waveform, between 0 and 1.
+    ///
+    /// Oscillators without a meaningful phase may ignore it, which is what the default
+    /// implementation does.
+    fn create_state_at(&self, phase: f32) -> Self::State {
+        let _ = phase;
+        self.create_state()
+    }
+
+    /// Move an existing state to the given `phase`, as [`Oscillator::create_state_at`] would
+    /// start it. Hard sync calls this on every cycle of its master.
+    ///
+    /// The default implementation replaces the state with a new one. Oscillators whose states
+    /// allocate, or are created with random values, should override it to reset the phase in
+    /// place.
+    fn reset_phase(&self, state: &mut Self::State, phase: f32) {
+        *state = self.create_state_at(phase);
+    }
+
     /// Fill the buffer with samples of the oscillator.
     ///
     /// The oscillator implementation should **add** its samples to the buffer, instead of
@@ -28,4 +63,73 @@ pub trait Oscillator {
         freq: f32,
         amp: f32,
     );
+
+    /// Fill the buffer with samples of the oscillator, with the frequency and amplitude given
+    /// separately for every sample. This allows vibrato, pitch bends and amplitude changes
+    /// without zipper noise.
+    ///
+    /// `freqs` and `amps` must be at least as long as `buffer`. The same rules as
+    /// [`Oscillator::fill_samples`] apply.
+    ///
+    /// The default implementation calls [`Oscillator::fill_samples`] once for every run of
+    /// samples sharing the same frequency and amplitude, which is correct but slow when both
+    /// change every sample. Oscillators are encouraged to override it.
+    fn fill_samples_modulated(
+        &self,
+        state: &mut Self::State,
+        buffer: &mut [f32],
+        delta_t: f32,
+        freqs: &[f32],
+        amps: &[f32],
+    ) {
+        let mut start = 0;
+        while start < buffer.len() {
+            let (freq, amp) = (freqs[start], amps[start]);
+            let run = freqs[start..buffer.len()]
+                .iter()
+                .zip(&amps[start..buffer.len()])
+                .take_while(|(f, a)| **f == freq && **a == amp)
+                .count();
+            let end = start + run;
+            self.fill_samples(state, &mut buffer[start..end], delta_t, freq, amp);
+            start = end;
+        }
+    }
+}
+
+#[test]
+fn test_default_modulated_fill_matches_scalar() {
+    let osc = wavetable::WavetableOscillator::from_harmonics(&[1.0, 0.5, 0.25]);
+    let delta_t = 1.0 / 44100.0;
+    let mut scalar = vec![0.0; 256];
+    let mut modulated = vec![0.0; 256];
+    osc.fill_samples(&mut osc.create_state(), &mut scalar, delta_t, 440.0, 0.5);
+    osc.fill_samples_modulated(
+        &mut osc.create_state(),
+        &mut modulated,
+        delta_t,
+        &[440.0; 256],
+        &[0.5; 256],
+    );
+    assert_eq!(scalar, modulated);
+}
+
+#[test]
+fn test_default_modulated_fill_follows_steps() {
+    let osc = wavetable::WavetableOscillator::from_harmonics(&[1.0, 0.5, 0.25]);
+    let delta_t = 1.0 / 44100.0;
+    let mut scalar = vec![0.0; 256];
+    let mut state = osc.create_state();
+    let (first, second) = scalar.split_at_mut(100);
+    osc.fill_samples(&mut state, first, delta_t, 440.0, 0.5);
+    osc.fill_samples(&mut state, second, delta_t, 660.0, 0.25);
+    let mut modulated = vec![0.0; 256];
+    osc.fill_samples_modulated(
+        &mut osc.create_state(),
+        &mut modulated,
+        delta_t,
+        &[[440.0; 100].as_slice(), &[660.0; 156]].concat(),
+        &[[0.5; 100].as_slice(), &[0.25; 156]].concat(),
+    );
+    assert_eq!(scalar, modulated);
 }
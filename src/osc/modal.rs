@@ -0,0 +1,233 @@
+//! Modal synthesis: a bank of exponentially decaying resonant modes struck at note start.
+//!
+//! Every mode is a two-pole resonator tuned to a multiple of the note frequency. The modes are
+//! excited once when the note starts, either with a single impulse or with a short burst of
+//! noise, and then ring out on their own.
+
+use super::{noise::NoiseOscillator, Oscillator};
+
+/// A single resonant mode.
+#[derive(Debug, Clone, Copy)]
+pub struct Mode {
+    /// The frequency of the mode relative to the note frequency.
+    pub ratio: f32,
+    /// The time it takes for the mode to decay by 60 dB, in seconds.
+    pub decay: f32,
+    /// The amplitude of the mode.
+    pub gain: f32,
+}
+
+impl Mode {
+    pub fn new(ratio: f32, decay: f32, gain: f32) -> Self {
+        Self { ratio, decay, gain }
+    }
+}
+
+/// What strikes the modes when a note starts.
+#[derive(Debug, Clone)]
+pub enum Excitation {
+    /// A single-sample impulse, like a hard mallet.
+    Impulse,
+    /// A burst of noise fading out linearly over `duration` seconds, like a soft mallet or a
+    /// scrape.
+    Noise {
+        noise: NoiseOscillator,
+        duration: f32,
+    },
+}
+
+#[derive(Debug, Clone)]
+pub struct ModalOscillator {
+    pub modes: Vec<Mode>,
+    pub excitation: Excitation,
+}
+
+impl ModalOscillator {
+    pub fn new(modes: Vec<Mode>) -> Self {
+        Self {
+            modes,
+            excitation: Excitation::Impulse,
+        }
+    }
+
+    pub fn with_noise_excitation(mut self, noise: NoiseOscillator, duration: f32) -> Self {
+        self.excitation = Excitation::Noise { noise, duration };
+        self
+    }
+
+    /// The first three modes of a tuned marimba bar.
+    pub fn marimba() -> Self {
+        Self::new(vec![
+            Mode::new(1.0, 1.2, 1.0),
+            Mode::new(3.99, 0.4, 0.35),
+            Mode::new(10.65, 0.12, 0.15),
+        ])
+    }
+
+    /// The partials of a church bell, named after their traditional roles. The note frequency
+    /// is the prime (the strike tone).
+    pub fn bell() -> Self {
+        Self::new(vec![
+            // hum
+            Mode::new(0.5, 6.0, 0.5),
+            // prime
+            Mode::new(1.0, 4.0, 1.0),
+            // tierce
+            Mode::new(1.2, 3.0, 0.6),
+            // quint
+            Mode::new(1.5, 2.5, 0.4),
+            // nominal
+            Mode::new(2.0, 2.0, 0.8),
+            Mode::new(2.5, 1.5, 0.3),
+            Mode::new(2.67, 1.2, 0.25),
+            Mode::new(3.0, 1.0, 0.2),
+            Mode::new(4.0, 0.7, 0.15),
+        ])
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ModalOscillatorState {
+    /// The last two outputs of every mode.
+    history: Vec<[f32; 2]>,
+    /// The number of samples rendered since the note started.
+    elapsed: usize,
+    noise: Option<<NoiseOscillator as Oscillator>::State>,
+    /// Scratch space for rendering the noise excitation.
+    noise_buf: Vec<f32>,
+}
+
+impl Oscillator for ModalOscillator {
+    type State = ModalOscillatorState;
+
+    fn create_state(&self) -> Self::State {
+        let noise = match &self.excitation {
+            Excitation::Impulse => None,
+            Excitation::Noise { noise, .. } => Some(noise.create_state()),
+        };
+        ModalOscillatorState {
+            history: vec![[0.0; 2]; self.modes.len()],
+            elapsed: 0,
+            noise,
+            noise_buf: vec![],
+        }
+    }
+
+    fn fill_samples(
+        &self,
+        state: &mut Self::State,
+        buffer: &mut [f32],
+        delta_t: f32,
+        freq: f32,
+        amp: f32,
+    ) {
+        // Render this buffer's share of the excitation.
+        state.noise_buf.clear();
+        state.noise_buf.resize(buffer.len(), 0.0);
+        match (&self.excitation, &mut state.noise) {
+            (Excitation::Impulse, _) => {
+                if state.elapsed == 0 && !buffer.is_empty() {
+                    state.noise_buf[0] = 1.0;
+                }
+            }
+            (Excitation::Noise { noise, duration }, Some(noise_state)) => {
+                let total = (duration / delta_t) as usize;
+                if state.elapsed < total {
+                    noise.fill_samples(noise_state, &mut state.noise_buf, delta_t, freq, 1.0);
+                    for (ix, x) in state.noise_buf.iter_mut().enumerate() {
+                        let n = state.elapsed + ix;
+                        *x *= 1.0 - (n as f32 / total as f32).min(1.0);
+                    }
+                }
+            }
+            (Excitation::Noise { .. }, None) => {}
+        }
+        state.elapsed += buffer.len();
+
+        let nyquist = 0.5 / delta_t;
+        for (mode, history) in self.modes.iter().zip(state.history.iter_mut()) {
+            let mode_freq = freq * mode.ratio;
+            if mode_freq >= nyquist || mode_freq <= 0.0 {
+                continue;
+            }
+            let omega = 2.0 * std::f32::consts::PI * mode_freq * delta_t;
+            let radius = 10.0_f32.powf(-3.0 * delta_t / mode.decay.max(f32::EPSILON));
+            let a1 = 2.0 * radius * omega.cos();
+            let a2 = -radius * radius;
+            // Scaling the input by sin(omega) makes an impulse ring at unit amplitude.
+            let input_gain = omega.sin() * mode.gain;
+
+            let [mut y1, mut y2] = *history;
+            for (sample, x) in buffer.iter_mut().zip(&state.noise_buf) {
+                let y = a1 * y1 + a2 * y2 + x * input_gain;
+                y2 = y1;
+                y1 = y;
+                *sample += y * amp;
+            }
+            *history = [y1, y2];
+        }
+    }
+}
+
+#[cfg(test)]
+fn render(osc: &ModalOscillator, len: usize, freq: f32) -> Vec<f32> {
+    let mut buf = vec![0.0; len];
+    let mut state = osc.create_state();
+    for chunk in buf.chunks_mut(441) {
+        osc.fill_samples(&mut state, chunk, 1.0 / 44100.0, freq, 1.0);
+    }
+    buf
+}
+
+#[cfg(test)]
+fn peak(samples: &[f32]) -> f32 {
+    samples.iter().fold(0.0, |peak, x| peak.max(x.abs()))
+}
+
+#[test]
+fn test_modes_ring_at_their_frequencies() {
+    for mode in ModalOscillator::marimba().modes {
+        let osc = ModalOscillator::new(vec![mode]);
+        let out = render(&osc, 4410, 220.0);
+        // The upward zero crossings of a single mode are one period apart.
+        let crossings = out
+            .windows(2)
+            .enumerate()
+            .filter(|(_, pair)| pair[0] <= 0.0 && pair[1] > 0.0)
+            .map(|(ix, pair)| ix as f32 + pair[0] / (pair[0] - pair[1]))
+            .collect::<Vec<_>>();
+        let periods = (crossings.len() - 1) as f32;
+        let measured = 44100.0 * periods / (crossings[crossings.len() - 1] - crossings[0]);
+        let expected = 220.0 * mode.ratio;
+        assert!(
+            (measured / expected - 1.0).abs() < 1e-3,
+            "{} != {}",
+            measured,
+            expected
+        );
+    }
+}
+
+#[test]
+fn test_mode_decays_by_60_db_in_its_decay_time() {
+    let osc = ModalOscillator::new(vec![Mode::new(1.0, 0.5, 1.0)]);
+    let out = render(&osc, 44100, 441.0);
+    // Compare the peaks of the first period and of the period starting at the decay time.
+    let start = peak(&out[..100]);
+    let end = peak(&out[22050..22150]);
+    assert!((start - 1.0).abs() < 0.01, "{}", start);
+    assert!((end / start / 1e-3 - 1.0).abs() < 0.05, "{}", end / start);
+}
+
+#[test]
+fn test_excitations_ring_and_decay() {
+    let noise = ModalOscillator::marimba()
+        .with_noise_excitation(NoiseOscillator::white().with_seed(1), 0.01);
+    for osc in [ModalOscillator::marimba(), noise] {
+        let out = render(&osc, 3 * 44100, 220.0);
+        let attack = peak(&out[..4410]);
+        assert!(attack > 0.1, "{}", attack);
+        // The longest mode decays by 60 dB in 1.2 seconds, so after 3 seconds it is far below.
+        assert!(peak(&out[3 * 44100 - 4410..]) < 1e-4 * attack);
+    }
+}
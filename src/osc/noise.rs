@@ -1,12 +1,181 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
 use super::Oscillator;
 
-use rand::Rng;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
 
-pub struct NoiseOscillator;
+/// The spectral slope of the generated noise.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum NoiseColor {
+    /// Equal power at every frequency.
+    #[default]
+    White,
+    /// -3 dB per octave.
+    Pink,
+    /// -6 dB per octave, also known as red or Brownian noise.
+    Brown,
+    /// +3 dB per octave.
+    Blue,
+    /// +6 dB per octave.
+    Violet,
+}
 
 #[derive(Debug, Clone, Default)]
+pub struct NoiseOscillator {
+    /// The color of the noise.
+    pub color: NoiseColor,
+    /// The seed of the random number generator; without one, every note is seeded from the
+    /// system's entropy.
+    ///
+    /// Seeded notes are reproducible, but not identical to each other: every note gets its own
+    /// stream of the seeded generator, numbered in the order the notes are started. A fresh
+    /// oscillator (or a clone) started with the same notes in the same order produces exactly the
+    /// same output. The generator is ChaCha8, whose output does not change between releases.
+    ///
+    /// The stream counter lives in the oscillator, which a [`Synth`](crate::Synth) shares
+    /// between all of its notes. The noise of a note therefore depends on how many notes were
+    /// started before it: starting notes in a different order, or starting an extra note, changes
+    /// the noise of every note after it.
+    pub seed: Option<u64>,
+    /// Whether the noise is sampled and held at the note frequency, instead of producing a new
+    /// value every sample.
+    pub sample_and_hold: bool,
+    rngs: NoteRngs,
+}
+
+impl NoiseOscillator {
+    pub fn new(color: NoiseColor) -> Self {
+        Self {
+            color,
+            seed: None,
+            sample_and_hold: false,
+            rngs: NoteRngs::default(),
+        }
+    }
+
+    pub fn white() -> Self {
+        Self::new(NoiseColor::White)
+    }
+
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    pub fn with_sample_and_hold(mut self) -> Self {
+        self.sample_and_hold = true;
+        self
+    }
+}
+
+/// Creates the random number generators of the notes of a seeded oscillator, see
+/// [`NoiseOscillator::seed`]. Every call takes the next stream, so the generator a note gets
+/// depends on the order in which notes are started.
+#[derive(Debug, Default)]
+pub(crate) struct NoteRngs {
+    /// The number of notes started so far.
+    notes: AtomicU64,
+}
+
+impl NoteRngs {
+    /// The generator for the next note.
+    pub(crate) fn next(&self, seed: Option<u64>) -> ChaCha8Rng {
+        match seed {
+            Some(seed) => {
+                let mut rng = ChaCha8Rng::seed_from_u64(seed);
+                rng.set_stream(self.notes.fetch_add(1, Ordering::Relaxed));
+                rng
+            }
+            None => ChaCha8Rng::from_entropy(),
+        }
+    }
+}
+
+impl Clone for NoteRngs {
+    fn clone(&self) -> Self {
+        Self {
+            notes: AtomicU64::new(self.notes.load(Ordering::Relaxed)),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct NoiseOscillatorState {
-    rng: rand::rngs::ThreadRng,
+    rng: ChaCha8Rng,
+    /// Filter memory used to color the noise.
+    filter: [f32; 7],
+    /// The previous output before differentiation, used by blue and violet noise.
+    prev: f32,
+    /// The value being held in sample-and-hold mode.
+    held: f32,
+    /// The phase of the sample-and-hold clock, between 0 and 1.
+    clock: f32,
+}
+
+/// An unseeded state, like the ones created by an unseeded oscillator.
+impl Default for NoiseOscillatorState {
+    fn default() -> Self {
+        Self {
+            rng: ChaCha8Rng::from_entropy(),
+            filter: [0.0; 7],
+            prev: 0.0,
+            held: 0.0,
+            // Start at the end of a clock cycle so that the first sample picks a new value.
+            clock: 1.0,
+        }
+    }
+}
+
+impl NoiseOscillatorState {
+    fn white(&mut self) -> f32 {
+        self.rng.gen_range(-1.0..1.0)
+    }
+
+    /// Paul Kellet's refined pink noise filter.
+    fn pink(&mut self) -> f32 {
+        let white = self.white();
+        let b = &mut self.filter;
+        b[0] = 0.99886 * b[0] + white * 0.0555179;
+        b[1] = 0.99332 * b[1] + white * 0.0750759;
+        b[2] = 0.96900 * b[2] + white * 0.153852;
+        b[3] = 0.86650 * b[3] + white * 0.3104856;
+        b[4] = 0.55000 * b[4] + white * 0.5329522;
+        b[5] = -0.7616 * b[5] - white * 0.0168980;
+        let pink = b[0] + b[1] + b[2] + b[3] + b[4] + b[5] + b[6] + white * 0.5362;
+        b[6] = white * 0.115926;
+        pink * 0.11
+    }
+
+    /// Leaky integration of white noise.
+    fn brown(&mut self) -> f32 {
+        let white = self.white();
+        self.filter[0] = (self.filter[0] + 0.02 * white) / 1.02;
+        self.filter[0] * 3.5
+    }
+
+    /// Differentiate `x` against the previous value.
+    fn differentiate(&mut self, x: f32) -> f32 {
+        let out = (x - self.prev) * 0.5;
+        self.prev = x;
+        out
+    }
+
+    fn next(&mut self, color: NoiseColor) -> f32 {
+        match color {
+            NoiseColor::White => self.white(),
+            NoiseColor::Pink => self.pink(),
+            NoiseColor::Brown => self.brown(),
+            NoiseColor::Blue => {
+                let pink = self.pink();
+                self.differentiate(pink) * 6.0
+            }
+            NoiseColor::Violet => {
+                let white = self.white();
+                self.differentiate(white)
+            }
+        }
+    }
 }
 
 impl Oscillator for NoiseOscillator {
@@ -14,7 +183,8 @@ impl Oscillator for NoiseOscillator {
 
     fn create_state(&self) -> Self::State {
         NoiseOscillatorState {
-            rng: rand::thread_rng(),
+            rng: self.rngs.next(self.seed),
+            ..NoiseOscillatorState::default()
         }
     }
 
@@ -22,12 +192,99 @@ impl Oscillator for NoiseOscillator {
         &self,
         state: &mut NoiseOscillatorState,
         buffer: &mut [f32],
-        _delta_t: f32,
-        _freq: f32,
+        delta_t: f32,
+        freq: f32,
         amp: f32,
     ) {
+        if !self.sample_and_hold {
+            for sample in buffer.iter_mut() {
+                *sample += state.next(self.color) * amp;
+            }
+            return;
+        }
+
+        let increment = freq * delta_t;
         for sample in buffer.iter_mut() {
-            *sample += state.rng.gen_range(-1.0..1.0) * amp;
+            if state.clock >= 1.0 {
+                state.clock %= 1.0;
+                state.held = state.next(self.color);
+            }
+            *sample += state.held * amp;
+            state.clock += increment;
+        }
+    }
+}
+
+#[test]
+fn test_seeded_noise_is_reproducible() {
+    let render = |osc: &NoiseOscillator| {
+        let mut buf = vec![0.0; 256];
+        osc.fill_samples(&mut osc.create_state(), &mut buf, 1.0 / 44100.0, 440.0, 1.0);
+        buf
+    };
+    let osc = NoiseOscillator::new(NoiseColor::Pink).with_seed(42);
+    let copy = osc.clone();
+    let (first, second) = (render(&osc), render(&osc));
+    // Notes of the same oscillator are not correlated...
+    assert_ne!(first, second);
+    // ...but the same notes in the same order are reproducible.
+    assert_eq!(render(&copy), first);
+    assert_eq!(render(&copy), second);
+}
+
+#[test]
+fn test_color_slopes() {
+    // Compare the power around 500 Hz with the power around 8 kHz, four octaves higher,
+    // averaged over 17 bins in each of 16 segments.
+    let segment = 4096;
+    let band_power = |samples: &[f32], freq: f32| {
+        let center = (freq * segment as f32 / 44100.0).round() as usize;
+        let mut power = 0.0;
+        for chunk in samples.chunks(segment) {
+            for bin in center - 8..=center + 8 {
+                let w = std::f32::consts::TAU * bin as f32 / segment as f32;
+                let (re, im) = chunk
+                    .iter()
+                    .enumerate()
+                    .fold((0.0, 0.0), |(re, im), (n, x)| {
+                        let (sin, cos) = (w * n as f32).sin_cos();
+                        (re + x * cos, im + x * sin)
+                    });
+                power += re * re + im * im;
+            }
         }
+        power
+    };
+    for (color, slope) in [
+        (NoiseColor::White, 0.0),
+        (NoiseColor::Pink, -3.0),
+        (NoiseColor::Brown, -6.0),
+        (NoiseColor::Blue, 3.0),
+        (NoiseColor::Violet, 6.0),
+    ] {
+        let osc = NoiseOscillator::new(color).with_seed(1);
+        let mut buf = vec![0.0; 16 * segment];
+        osc.fill_samples(&mut osc.create_state(), &mut buf, 1.0 / 44100.0, 440.0, 1.0);
+        let octaves = 4.0;
+        let db = 10.0 * (band_power(&buf, 8000.0) / band_power(&buf, 500.0)).log10();
+        assert!(
+            (db - slope * octaves).abs() < 2.0,
+            "{:?}: {} dB over {} octaves",
+            color,
+            db,
+            octaves
+        );
+    }
+}
+
+#[test]
+fn test_sample_and_hold_changes_once_per_cycle() {
+    // At 16 Hz and 1024 samples per second, every value is held for exactly 64 samples.
+    let osc = NoiseOscillator::white().with_seed(1).with_sample_and_hold();
+    let mut buf = vec![0.0; 1024];
+    osc.fill_samples(&mut osc.create_state(), &mut buf, 1.0 / 1024.0, 16.0, 1.0);
+    for (ix, pair) in buf.windows(2).enumerate() {
+        let held = (ix + 1) % 64 != 0;
+        assert_eq!(pair[0] == pair[1], held, "at {}", ix);
     }
 }
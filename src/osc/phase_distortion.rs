@@ -0,0 +1,155 @@
+//! Phase distortion synthesis in the style of the Casio CZ series.
+//!
+//! A cosine is read out with a warped phase: the phase runs through parts of the cycle faster
+//! than others, which adds harmonics much like opening a low-pass filter. Sweeping the amount
+//! from 0 to 1 gives the typical filter sweep without an actual filter.
+
+use std::f32::consts::TAU;
+
+use super::{
+    sine::{constant_step, modulated_step, SineOscillatorState},
+    Oscillator,
+};
+
+/// The transfer function applied to the phase.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PhaseDistortionShape {
+    /// The first half of the cosine is compressed into the start of the cycle, turning it into
+    /// a sawtooth as the amount increases.
+    #[default]
+    Saw,
+    /// Each half of the cosine is compressed into the start of its half cycle and then held,
+    /// turning it into a square wave as the amount increases.
+    Square,
+    /// A cosine at a higher frequency, restarted every cycle and faded in and out by a triangle
+    /// window. The amount sets the frequency of the cosine from 1 to 16 times the note frequency.
+    ResonantPulse,
+}
+
+#[derive(Debug, Clone)]
+pub struct PhaseDistortionOscillator {
+    pub shape: PhaseDistortionShape,
+    /// How strongly the phase is distorted, between 0 and 1. For [`PhaseDistortionShape::Saw`]
+    /// and [`PhaseDistortionShape::Square`], 0 is a pure cosine.
+    pub amount: f32,
+}
+
+impl PhaseDistortionOscillator {
+    pub fn new(shape: PhaseDistortionShape, amount: f32) -> Self {
+        Self { shape, amount }
+    }
+
+    /// The output at a phase between 0 and 1.
+    fn shape(&self, phase: f32) -> f32 {
+        let amount = self.amount.clamp(0.0, 1.0);
+        match self.shape {
+            PhaseDistortionShape::Saw => {
+                // The breakpoint where the warped phase reaches one half.
+                let knee = 0.5 - 0.49 * amount;
+                let warped = if phase < knee {
+                    0.5 * phase / knee
+                } else {
+                    0.5 + 0.5 * (phase - knee) / (1.0 - knee)
+                };
+                (TAU * warped).cos()
+            }
+            PhaseDistortionShape::Square => {
+                let width = 0.5 - 0.49 * amount;
+                let half = if phase < 0.5 { 0.0 } else { 0.5 };
+                let warped = half + 0.5 * ((phase - half) / width).min(1.0);
+                (TAU * warped).cos()
+            }
+            PhaseDistortionShape::ResonantPulse => {
+                let resonance = 1.0 + 15.0 * amount;
+                let window = 1.0 - (2.0 * phase - 1.0).abs();
+                1.0 - (1.0 - (TAU * resonance * phase).cos()) * window
+            }
+        }
+    }
+
+    /// Render samples, with `step` as in [`SineOscillatorState::render`].
+    fn render(
+        &self,
+        state: &mut PhaseDistortionOscillatorState,
+        buffer: &mut [f32],
+        delta_t: f32,
+        step: impl FnMut(usize) -> (f32, f32),
+    ) {
+        // The resonant pulse is not symmetric, so its DC offset is removed with a one-pole
+        // high-pass at 10 Hz.
+        let dc_block = self.shape == PhaseDistortionShape::ResonantPulse;
+        let pole = (-TAU * 10.0 * delta_t).exp();
+        let (dc_in, dc_out) = (&mut state.dc_in, &mut state.dc_out);
+        state.phase.render(buffer, step, |phase| {
+            let out = self.shape(phase / TAU);
+            if !dc_block {
+                return out;
+            }
+            *dc_out = out - *dc_in + pole * *dc_out;
+            *dc_in = out;
+            *dc_out
+        });
+    }
+}
+
+impl Default for PhaseDistortionOscillator {
+    fn default() -> Self {
+        Self::new(PhaseDistortionShape::default(), 0.5)
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct PhaseDistortionOscillatorState {
+    phase: SineOscillatorState,
+    /// The last input and output of the DC blocker.
+    dc_in: f32,
+    dc_out: f32,
+}
+
+impl Oscillator for PhaseDistortionOscillator {
+    type State = PhaseDistortionOscillatorState;
+
+    fn create_state(&self) -> Self::State {
+        PhaseDistortionOscillatorState::default()
+    }
+
+    fn create_state_at(&self, phase: f32) -> Self::State {
+        PhaseDistortionOscillatorState {
+            phase: SineOscillatorState::at(phase),
+            ..Default::default()
+        }
+    }
+
+    fn fill_samples(
+        &self,
+        state: &mut Self::State,
+        buffer: &mut [f32],
+        delta_t: f32,
+        freq: f32,
+        amp: f32,
+    ) {
+        self.render(state, buffer, delta_t, constant_step(delta_t, freq, amp));
+    }
+
+    fn fill_samples_modulated(
+        &self,
+        state: &mut Self::State,
+        buffer: &mut [f32],
+        delta_t: f32,
+        freqs: &[f32],
+        amps: &[f32],
+    ) {
+        self.render(state, buffer, delta_t, modulated_step(delta_t, freqs, amps));
+    }
+}
+
+#[test]
+fn test_no_distortion_is_a_cosine() {
+    for shape in [PhaseDistortionShape::Saw, PhaseDistortionShape::Square] {
+        let osc = PhaseDistortionOscillator::new(shape, 0.0);
+        for i in 0..100 {
+            let phase = i as f32 / 100.0;
+            assert!((osc.shape(phase) - (TAU * phase).cos()).abs() < 1e-5);
+        }
+    }
+}
@@ -0,0 +1,231 @@
+//! A plucked string using the extended Karplus-Strong algorithm (Jaffe and Smith, 1983).
+//!
+//! A burst of noise one period long is fed into a delay line tuned to the note frequency. The
+//! delay line loops through a two-point averaging filter, an optional one-pole damping filter
+//! and an allpass filter providing the fractional part of the delay, so that the string stays
+//! in tune at high frequencies.
+
+use super::{noise::NoiseOscillator, Oscillator};
+
+#[derive(Debug, Clone)]
+pub struct PluckOscillator {
+    /// The time it takes for the string to decay by 60 dB, in seconds. The averaging and
+    /// damping filters shorten high notes further.
+    pub decay: f32,
+    /// How much the one-pole damping filter darkens the string, between 0 (no extra damping)
+    /// and 1 (exclusive). Higher values make the tone duller and the decay faster.
+    pub damping: f32,
+    /// The weight of the older sample in the two-point averaging filter, between 0 and 1. 0.5
+    /// is the original Karplus-Strong filter; values further from 0.5 make high partials decay
+    /// more slowly.
+    pub stretch: f32,
+    /// Where the string is picked, as a fraction of its length from the bridge. 0 disables the
+    /// pick position filter; values around 0.1 to 0.5 give increasingly hollow tones.
+    pub pick_position: f32,
+    /// The noise used to excite the string.
+    pub excitation: NoiseOscillator,
+}
+
+impl PluckOscillator {
+    pub fn new(decay: f32) -> Self {
+        Self {
+            decay,
+            damping: 0.0,
+            stretch: 0.5,
+            pick_position: 0.0,
+            excitation: NoiseOscillator::white(),
+        }
+    }
+
+    pub fn with_damping(mut self, damping: f32) -> Self {
+        self.damping = damping;
+        self
+    }
+
+    pub fn with_stretch(mut self, stretch: f32) -> Self {
+        self.stretch = stretch;
+        self
+    }
+
+    pub fn with_pick_position(mut self, pick_position: f32) -> Self {
+        self.pick_position = pick_position;
+        self
+    }
+
+    pub fn with_excitation(mut self, excitation: NoiseOscillator) -> Self {
+        self.excitation = excitation;
+        self
+    }
+
+    /// Generate the excitation burst of the given length with the pick position filter applied.
+    fn excite(&self, len: usize, delta_t: f32, freq: f32) -> Vec<f32> {
+        let mut noise = vec![0.0; len];
+        let mut noise_state = self.excitation.create_state();
+        self.excitation
+            .fill_samples(&mut noise_state, &mut noise, delta_t, freq, 1.0);
+
+        // The string loop keeps any DC offset forever, so remove it up front.
+        let mean = noise.iter().sum::<f32>() / len.max(1) as f32;
+        noise.iter_mut().for_each(|x| *x -= mean);
+
+        let pick = (self.pick_position.clamp(0.0, 1.0) * len as f32).round() as usize;
+        if pick == 0 {
+            return noise;
+        }
+        (0..len)
+            .map(|n| noise[n] - if n >= pick { noise[n - pick] } else { 0.0 })
+            .collect()
+    }
+}
+
+impl Default for PluckOscillator {
+    fn default() -> Self {
+        Self::new(4.0)
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct PluckOscillatorState {
+    /// The delay line, used as a ring buffer. Empty until the first samples are rendered, since
+    /// its length depends on the note frequency.
+    line: Vec<f32>,
+    /// The next position in `line` to write to.
+    write: usize,
+    /// The excitation burst fed into the string.
+    excitation: Vec<f32>,
+    /// The next position in `excitation` to read from.
+    excitation_pos: usize,
+    /// The last output of the damping filter.
+    lowpass: f32,
+    /// The last input and output of the tuning allpass filter.
+    allpass_in: f32,
+    allpass_out: f32,
+}
+
+impl PluckOscillatorState {
+    fn tap(&self, delay: usize) -> f32 {
+        let len = self.line.len();
+        self.line[(self.write + len - delay) % len]
+    }
+}
+
+impl Oscillator for PluckOscillator {
+    type State = PluckOscillatorState;
+
+    fn create_state(&self) -> Self::State {
+        PluckOscillatorState::default()
+    }
+
+    fn fill_samples(
+        &self,
+        state: &mut Self::State,
+        buffer: &mut [f32],
+        delta_t: f32,
+        freq: f32,
+        amp: f32,
+    ) {
+        if freq <= 0.0 {
+            return;
+        }
+        let period = 1.0 / (freq * delta_t);
+        if state.line.is_empty() {
+            // Leave room for bending the note down by an octave.
+            state.line = vec![0.0; (2.0 * period).ceil() as usize + 4];
+            state.excitation = self.excite(period.round() as usize, delta_t, freq);
+        }
+
+        let stretch = self.stretch.clamp(0.0, 1.0);
+        let damping = self.damping.clamp(0.0, 0.999);
+        // Subtract the phase delay of the loop filters at the note frequency from the delay line
+        // length. At high notes it differs noticeably from the delay at DC.
+        let w = std::f32::consts::TAU * freq * delta_t;
+        let (sin, cos) = w.sin_cos();
+        let averaging_delay = (stretch * sin).atan2(1.0 - stretch + stretch * cos) / w;
+        let damping_delay = (damping * sin).atan2(1.0 - damping * cos) / w;
+        let delay = (period - averaging_delay - damping_delay).max(1.1);
+        let mut whole = delay.floor() as usize;
+        let mut frac = delay - whole as f32;
+        // Keep the allpass delay within 0.1..1.1 samples, where its phase delay is flat enough.
+        if frac < 0.1 && whole > 1 {
+            whole -= 1;
+            frac += 1.0;
+        }
+        let whole = whole.min(state.line.len() - 2);
+        // The allpass coefficient whose phase delay at the note frequency is `frac`.
+        let allpass = ((1.0 - frac) * 0.5 * w).sin() / ((1.0 + frac) * 0.5 * w).sin();
+        let gain = 10.0_f32.powf(-3.0 / (self.decay.max(f32::EPSILON) * freq));
+
+        for sample in buffer.iter_mut() {
+            let averaged = (1.0 - stretch) * state.tap(whole) + stretch * state.tap(whole + 1);
+            state.lowpass = (1.0 - damping) * averaged + damping * state.lowpass;
+            let tuned = allpass * state.lowpass + state.allpass_in - allpass * state.allpass_out;
+            state.allpass_in = state.lowpass;
+            state.allpass_out = tuned;
+
+            let input = match state.excitation.get(state.excitation_pos) {
+                Some(x) => {
+                    state.excitation_pos += 1;
+                    *x
+                }
+                None => 0.0,
+            };
+            let out = input + gain * tuned;
+            let len = state.line.len();
+            state.line[state.write] = out;
+            state.write = (state.write + 1) % len;
+            *sample += out * amp;
+        }
+    }
+}
+
+#[test]
+fn test_pluck_is_in_tune() {
+    use std::f64::consts::{PI, TAU};
+
+    let sample_rate = 44100.0;
+    let strings = [
+        PluckOscillator::default(),
+        PluckOscillator::default().with_damping(0.2),
+        PluckOscillator::default().with_stretch(0.2),
+        PluckOscillator::default()
+            .with_damping(0.3)
+            .with_stretch(0.8),
+    ];
+    for osc in strings {
+        let osc = osc.with_excitation(NoiseOscillator::white().with_seed(1));
+        for freq in [220.0, 880.0, 2000.0, 3520.0] {
+            // Measure how far the phase of the fundamental drifts against `freq` between two
+            // windows a few periods apart, each long enough to separate it from the harmonics.
+            let period = sample_rate / freq;
+            let (start, len, shift) = (
+                (2.0 * period) as usize,
+                (32.0 * period) as usize,
+                (4.0 * period) as usize,
+            );
+            let mut buf = vec![0.0; start + len + shift];
+            osc.fill_samples(
+                &mut osc.create_state(),
+                &mut buf,
+                1.0 / sample_rate,
+                freq,
+                1.0,
+            );
+            let w = TAU * freq as f64 / sample_rate as f64;
+            let phase = |start: usize| {
+                let (re, im) = buf[start..start + len].iter().enumerate().fold(
+                    (0.0, 0.0),
+                    |(re, im), (n, x)| {
+                        let window = 0.5 - 0.5 * (TAU * n as f64 / len as f64).cos();
+                        let (sin, cos) = (w * (start + n) as f64).sin_cos();
+                        (re + *x as f64 * window * cos, im - *x as f64 * window * sin)
+                    },
+                );
+                im.atan2(re)
+            };
+            let drift = (phase(start + shift) - phase(start) + PI).rem_euclid(TAU) - PI;
+            let actual = freq as f64 * (1.0 + drift / (w * shift as f64));
+            let cents = 1200.0 * (actual / freq as f64).log2();
+            assert!(cents.abs() < 1.0, "{} cents off at {} Hz", cents, freq);
+        }
+    }
+}
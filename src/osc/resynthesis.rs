@@ -0,0 +1,246 @@
+//! Additive resynthesis: approximating a recorded note with a sum of sine partials.
+//!
+//! [`analyze`] takes a segment from the middle of the recording, where the note is usually
+//! steady, and computes its spectrum with an FFT. The fundamental is estimated with a harmonic
+//! product spectrum; then the strongest peak near every multiple of the fundamental becomes a
+//! partial. Peaks slightly off the exact multiple are kept where they are, preserving the
+//! inharmonicity of e.g. piano strings.
+//!
+//! Only the magnitudes of the partials are measured; their phases are discarded.
+
+use std::f64::consts::PI;
+
+use super::{
+    harmonic::{HarmonicOscillator, Partial, Rolloff},
+    wavetable::WavetableOscillator,
+};
+
+/// The longest segment analyzed, in samples.
+const MAX_WINDOW: usize = 1 << 15;
+/// The shortest recording that can be analyzed, in samples.
+const MIN_WINDOW: usize = 1 << 10;
+/// The number of harmonics multiplied by the harmonic product spectrum.
+const HPS_HARMONICS: usize = 4;
+/// The range of fundamentals considered, in Hz.
+const MIN_FUNDAMENTAL: f64 = 20.0;
+const MAX_FUNDAMENTAL: f64 = 4000.0;
+/// Partials quieter than this, relative to the loudest one, are dropped (-80 dB).
+const THRESHOLD: f64 = 1e-4;
+
+/// The result of analyzing a recorded note.
+#[derive(Debug, Clone)]
+pub struct Analysis {
+    /// The estimated fundamental frequency, in Hz.
+    pub fundamental: f32,
+    /// The partials relative to the fundamental. The loudest one has an amplitude of 1.
+    pub partials: Vec<Partial>,
+}
+
+impl Analysis {
+    /// An oscillator playing the analyzed partials.
+    pub fn harmonic_oscillator(&self) -> HarmonicOscillator {
+        HarmonicOscillator::from_partials(&self.partials, Rolloff::None)
+    }
+
+    /// A single-frame wavetable of the analyzed partials. Wavetables can only hold harmonic
+    /// partials, so every partial is rounded to the nearest harmonic.
+    pub fn wavetable(&self) -> WavetableOscillator {
+        let count = self
+            .partials
+            .iter()
+            .map(|p| p.ratio.round() as usize)
+            .max()
+            .unwrap_or(0);
+        let mut amps = vec![0.0; count];
+        for partial in &self.partials {
+            let harmonic = partial.ratio.round() as usize;
+            if harmonic >= 1 {
+                // Undo the 1/n rolloff applied by `from_harmonics`.
+                amps[harmonic - 1] += partial.amplitude * harmonic as f32;
+            }
+        }
+        WavetableOscillator::from_harmonics(&amps)
+    }
+}
+
+/// Analyze a recording of a single note, keeping at most `max_partials` partials.
+///
+/// Returns `None` if the recording is shorter than 1024 samples, silent, or has no detectable
+/// fundamental between 20 Hz and 4 kHz.
+pub fn analyze(samples: &[f32], sample_rate: f32, max_partials: usize) -> Option<Analysis> {
+    if samples.len() < MIN_WINDOW {
+        return None;
+    }
+    let len = prev_power_of_two(samples.len()).min(MAX_WINDOW);
+    let start = (samples.len() - len) / 2;
+    let sample_rate = sample_rate as f64;
+    let bin_width = sample_rate / len as f64;
+
+    // Hann-windowed spectrum.
+    let mut re = samples[start..start + len]
+        .iter()
+        .enumerate()
+        .map(|(n, x)| *x as f64 * 0.5 * (1.0 - (2.0 * PI * n as f64 / len as f64).cos()))
+        .collect::<Vec<_>>();
+    let mut im = vec![0.0; len];
+    fft(&mut re, &mut im);
+    let mags = re
+        .iter()
+        .zip(&im)
+        .take(len / 2)
+        .map(|(re, im)| re.hypot(*im))
+        .collect::<Vec<_>>();
+
+    let fundamental = estimate_fundamental(&mags, bin_width)?;
+    let fundamental_bins = fundamental / bin_width;
+    let search = (fundamental_bins * 0.25).max(1.0);
+
+    let mut peaks = vec![];
+    for harmonic in (1..=max_partials).map(|k| k as f64) {
+        let center = harmonic * fundamental_bins;
+        let lo = (center - search).floor().max(1.0) as usize;
+        let hi = ((center + search).ceil() as usize).min(mags.len() - 2);
+        if lo > hi {
+            break;
+        }
+        let peak = (lo..=hi).max_by(|a, b| mags[*a].total_cmp(&mags[*b]))?;
+        let (bin, mag) = interpolate_peak(&mags, peak);
+        peaks.push((bin / fundamental_bins, mag));
+    }
+
+    let loudest = peaks.iter().map(|(_, mag)| *mag).fold(0.0, f64::max);
+    if loudest <= 0.0 {
+        return None;
+    }
+    let partials = peaks
+        .into_iter()
+        .filter(|(_, mag)| *mag >= loudest * THRESHOLD)
+        .map(|(ratio, mag)| Partial::new(ratio as f32, (mag / loudest) as f32, 0.0))
+        .collect();
+    Some(Analysis {
+        fundamental: fundamental as f32,
+        partials,
+    })
+}
+
+fn prev_power_of_two(n: usize) -> usize {
+    1 << (usize::BITS - 1 - n.leading_zeros())
+}
+
+/// The fundamental in Hz, from the peak of the harmonic product spectrum.
+fn estimate_fundamental(mags: &[f64], bin_width: f64) -> Option<f64> {
+    let floor = mags.iter().cloned().fold(0.0, f64::max) * 1e-9;
+    if floor <= 0.0 {
+        return None;
+    }
+    let lo = ((MIN_FUNDAMENTAL / bin_width).ceil() as usize).max(1);
+    let hi = ((MAX_FUNDAMENTAL / bin_width) as usize).min((mags.len() - 1) / HPS_HARMONICS);
+    let candidate = (lo..=hi).max_by(|a, b| {
+        let hps = |bin: usize| -> f64 {
+            (1..=HPS_HARMONICS)
+                .map(|h| (mags[bin * h] + floor).ln())
+                .sum()
+        };
+        hps(*a).total_cmp(&hps(*b))
+    })?;
+
+    // The product spectrum is coarse; refine on the nearest peak of the actual spectrum.
+    let peak = (candidate.saturating_sub(1).max(1)..=(candidate + 1).min(mags.len() - 2))
+        .max_by(|a, b| mags[*a].total_cmp(&mags[*b]))?;
+    Some(interpolate_peak(mags, peak).0 * bin_width)
+}
+
+/// Locate the sinusoid behind the spectral peak at `peak`, returning its fractional bin and its
+/// magnitude.
+///
+/// The bin is the vertex of a parabola through the log magnitudes around the peak. The
+/// magnitude is corrected for the response of the Hann window at the bin's distance from the
+/// peak.
+fn interpolate_peak(mags: &[f64], peak: usize) -> (f64, f64) {
+    let ln = |bin: usize| mags[bin].max(f64::MIN_POSITIVE).ln();
+    let (a, b, c) = (ln(peak - 1), ln(peak), ln(peak + 1));
+    let denom = a - 2.0 * b + c;
+    if denom >= 0.0 {
+        // Not a local maximum.
+        return (peak as f64, mags[peak]);
+    }
+    let offset = (0.5 * (a - c) / denom).clamp(-0.5, 0.5);
+    (peak as f64 + offset, mags[peak] / hann_response(offset))
+}
+
+/// The magnitude response of a Hann window `offset` bins away from a sinusoid, relative to its
+/// response at the sinusoid itself.
+fn hann_response(offset: f64) -> f64 {
+    if offset.abs() < 1e-9 {
+        return 1.0;
+    }
+    let x = PI * offset;
+    x.sin() / (x * (1.0 - offset * offset))
+}
+
+/// An in-place radix-2 FFT. The length must be a power of two.
+fn fft(re: &mut [f64], im: &mut [f64]) {
+    let n = re.len();
+    let mut j = 0;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+        if i < j {
+            re.swap(i, j);
+            im.swap(i, j);
+        }
+    }
+
+    let mut size = 2;
+    while size <= n {
+        let step = -2.0 * PI / size as f64;
+        for start in (0..n).step_by(size) {
+            for k in 0..size / 2 {
+                let (w_im, w_re) = (step * k as f64).sin_cos();
+                let (a, b) = (start + k, start + k + size / 2);
+                let t_re = re[b] * w_re - im[b] * w_im;
+                let t_im = re[b] * w_im + im[b] * w_re;
+                re[b] = re[a] - t_re;
+                im[b] = im[a] - t_im;
+                re[a] += t_re;
+                im[a] += t_im;
+            }
+        }
+        size *= 2;
+    }
+}
+
+#[test]
+fn test_analyze_recovers_partials() {
+    use super::Oscillator;
+
+    let amps = [1.0, 0.5, 0.25, 0.125];
+    let source = HarmonicOscillator::from_partials(
+        &amps
+            .iter()
+            .enumerate()
+            .map(|(ix, amp)| Partial::new((ix + 1) as f32, *amp, 0.0))
+            .collect::<Vec<_>>(),
+        Rolloff::None,
+    );
+    let mut recording = vec![0.0; 44100];
+    source.fill_samples(
+        &mut source.create_state(),
+        &mut recording,
+        1.0 / 44100.0,
+        220.0,
+        0.5,
+    );
+
+    let analysis = analyze(&recording, 44100.0, 8).unwrap();
+    assert!((analysis.fundamental - 220.0).abs() < 0.1);
+    assert_eq!(analysis.partials.len(), amps.len());
+    for (partial, (ix, amp)) in analysis.partials.iter().zip(amps.iter().enumerate()) {
+        assert!((partial.ratio - (ix + 1) as f32).abs() < 1e-3);
+        assert!((partial.amplitude - amp).abs() < 0.01);
+    }
+}
@@ -0,0 +1,306 @@
+//! Playback of recorded PCM audio, repitched according to the note frequency.
+
+use super::Oscillator;
+
+/// How the playback position continues after reaching the end of the loop region.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LoopMode {
+    /// Play the sample once and then stay silent.
+    #[default]
+    None,
+    /// Jump back to the loop start after reaching the loop end.
+    Forward,
+    /// Bounce back and forth between the first and the last sample of the loop.
+    PingPong,
+}
+
+/// How samples are read between two points of the source data.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Interpolation {
+    /// Linear interpolation between two neighbouring points.
+    #[default]
+    Linear,
+    /// 4-point cubic Hermite interpolation.
+    Cubic,
+    /// Blackman-windowed sinc interpolation over 16 points, low-passed when pitching upwards.
+    Sinc,
+}
+
+/// The number of points on each side of the read position used by sinc interpolation.
+const SINC_HALF_WIDTH: isize = 8;
+
+#[derive(Debug, Clone)]
+pub struct SamplerOscillator {
+    /// The mono PCM data.
+    data: Vec<f32>,
+    /// The sample rate of `data`, in Hz.
+    sample_rate: f32,
+    /// The frequency the sample plays at when not repitched, in Hz.
+    root_freq: f32,
+    loop_mode: LoopMode,
+    /// The loop region, in samples of `data`.
+    loop_start: usize,
+    loop_end: usize,
+    /// The length of the crossfade before the loop end in forward loops, in samples of `data`.
+    crossfade: usize,
+    /// The interpolation used when reading `data`.
+    interpolation: Interpolation,
+}
+
+impl SamplerOscillator {
+    /// Create a one-shot sampler from PCM data recorded at `sample_rate`, whose pitch is
+    /// `root_freq`.
+    pub fn new(data: Vec<f32>, sample_rate: f32, root_freq: f32) -> Self {
+        let len = data.len();
+        Self {
+            data,
+            sample_rate,
+            root_freq,
+            loop_mode: LoopMode::None,
+            loop_start: 0,
+            loop_end: len,
+            crossfade: 0,
+            interpolation: Interpolation::Linear,
+        }
+    }
+
+    /// Loop the region `start..end`, in samples of the source data.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the region is empty or exceeds the data.
+    pub fn with_loop(mut self, mode: LoopMode, start: usize, end: usize) -> Self {
+        assert!(start < end, "the loop region is empty");
+        assert!(end <= self.data.len(), "the loop end exceeds the data");
+        self.loop_mode = mode;
+        self.loop_start = start;
+        self.loop_end = end;
+        self
+    }
+
+    /// Crossfade the end of a forward loop into the audio preceding its start, over `samples`
+    /// samples of the source data. This hides clicks at loop points that are not perfectly
+    /// matched. The crossfade is limited by the loop length and by the audio available before
+    /// the loop start.
+    pub fn with_crossfade(mut self, samples: usize) -> Self {
+        self.crossfade = samples;
+        self
+    }
+
+    pub fn with_interpolation(mut self, interpolation: Interpolation) -> Self {
+        self.interpolation = interpolation;
+        self
+    }
+
+    /// The data point at `ix`. Points past the loop end are mapped back into the loop, so that
+    /// interpolation across the loop end reads the audio that actually plays next. With
+    /// `wrap_below`, the same is done for points before the loop start.
+    fn point(&self, ix: isize, wrap_below: bool) -> f32 {
+        let (start, end) = (self.loop_start as isize, self.loop_end as isize);
+        let ix = match self.loop_mode {
+            LoopMode::None => ix,
+            _ if ix < end && (ix >= start || !wrap_below) => ix,
+            LoopMode::Forward => start + (ix - start).rem_euclid(end - start),
+            LoopMode::PingPong => {
+                // Mirror around the first and the last sample of the loop.
+                let last = end - 1;
+                let period = 2 * (last - start);
+                if period == 0 {
+                    start
+                } else {
+                    let k = (ix - start).rem_euclid(period);
+                    start + k.min(period - k)
+                }
+            }
+        };
+        if ix < 0 {
+            0.0
+        } else {
+            self.data.get(ix as usize).copied().unwrap_or(0.0)
+        }
+    }
+
+    /// Read the data at a fractional position. `step` is the playback speed, used to low-pass
+    /// the sinc kernel when pitching upwards. See [`SamplerOscillator::point`] for `wrap_below`.
+    fn read(&self, pos: f64, step: f64, wrap_below: bool) -> f32 {
+        let base = pos.floor();
+        let frac = (pos - base) as f32;
+        let ix = base as isize;
+        let point = |ix| self.point(ix, wrap_below);
+        match self.interpolation {
+            Interpolation::Linear => {
+                let a = point(ix);
+                let b = point(ix + 1);
+                a + (b - a) * frac
+            }
+            Interpolation::Cubic => {
+                let y0 = point(ix - 1);
+                let y1 = point(ix);
+                let y2 = point(ix + 1);
+                let y3 = point(ix + 2);
+                let c1 = 0.5 * (y2 - y0);
+                let c2 = y0 - 2.5 * y1 + 2.0 * y2 - 0.5 * y3;
+                let c3 = 0.5 * (y3 - y0) + 1.5 * (y1 - y2);
+                ((c3 * frac + c2) * frac + c1) * frac + y1
+            }
+            Interpolation::Sinc => {
+                let cutoff = (1.0 / step.abs()).min(1.0) as f32;
+                let mut sum = 0.0;
+                for k in (1 - SINC_HALF_WIDTH)..=SINC_HALF_WIDTH {
+                    let x = k as f32 - frac;
+                    sum += point(ix + k) * cutoff * sinc(x * cutoff) * blackman(x);
+                }
+                sum
+            }
+        }
+    }
+
+    fn crossfade_len(&self) -> usize {
+        self.crossfade
+            .min(self.loop_start)
+            .min(self.loop_end - self.loop_start)
+    }
+}
+
+fn sinc(x: f32) -> f32 {
+    if x == 0.0 {
+        1.0
+    } else {
+        let x = std::f32::consts::PI * x;
+        x.sin() / x
+    }
+}
+
+/// A Blackman window spanning `-SINC_HALF_WIDTH..SINC_HALF_WIDTH`.
+fn blackman(x: f32) -> f32 {
+    let n = (x / SINC_HALF_WIDTH as f32 + 1.0) * 0.5;
+    if !(0.0..=1.0).contains(&n) {
+        return 0.0;
+    }
+    let t = 2.0 * std::f32::consts::PI * n;
+    0.42 - 0.5 * t.cos() + 0.08 * (2.0 * t).cos()
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SamplerOscillatorState {
+    /// The read position, in samples of the source data.
+    position: f64,
+    /// Whether a ping-pong loop is currently playing backwards.
+    reverse: bool,
+    /// Whether the loop has wrapped around or bounced at least once.
+    looped: bool,
+}
+
+impl Oscillator for SamplerOscillator {
+    type State = SamplerOscillatorState;
+
+    fn create_state(&self) -> Self::State {
+        SamplerOscillatorState::default()
+    }
+
+    fn fill_samples(
+        &self,
+        state: &mut Self::State,
+        buffer: &mut [f32],
+        delta_t: f32,
+        freq: f32,
+        amp: f32,
+    ) {
+        let step = (freq / self.root_freq * self.sample_rate * delta_t) as f64;
+        let loop_start = self.loop_start as f64;
+        let loop_end = self.loop_end as f64;
+        let loop_len = loop_end - loop_start;
+        let crossfade = self.crossfade_len() as f64;
+        let last = (self.loop_end - 1) as f64;
+
+        for sample in buffer.iter_mut() {
+            let pos = state.position;
+            // A crossfade makes the audio before the loop start lead into it, so only wrap
+            // points before the start when there is none.
+            let wrap_below = state.looped && crossfade == 0.0;
+            let out = match self.loop_mode {
+                LoopMode::None if pos >= self.data.len() as f64 => break,
+                LoopMode::Forward if crossfade > 0.0 && pos >= loop_end - crossfade => {
+                    // Fade the loop end out while fading in the audio leading into the start.
+                    let t = ((pos - (loop_end - crossfade)) / crossfade) as f32;
+                    let tail = self.read(pos, step, wrap_below);
+                    let head = self.read(pos - loop_len, step, wrap_below);
+                    tail * (1.0 - t) + head * t
+                }
+                _ => self.read(pos, step, wrap_below),
+            };
+            *sample += out * amp;
+
+            match self.loop_mode {
+                LoopMode::None => state.position += step,
+                LoopMode::Forward => {
+                    state.position += step;
+                    if state.position >= loop_end {
+                        state.position = loop_start + (state.position - loop_end) % loop_len;
+                        state.looped = true;
+                    }
+                }
+                LoopMode::PingPong => {
+                    if state.reverse {
+                        state.position -= step;
+                        if state.position < loop_start {
+                            state.position = (2.0 * loop_start - state.position).min(last);
+                            state.reverse = false;
+                        }
+                    } else {
+                        state.position += step;
+                        if state.position > last {
+                            state.position = (2.0 * last - state.position).max(loop_start);
+                            state.reverse = true;
+                            state.looped = true;
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn test_sampler_plays_at_root_frequency() {
+    let data = (0..32).map(|x| x as f32 / 32.0).collect::<Vec<_>>();
+    let sampler = SamplerOscillator::new(data.clone(), 44100.0, 440.0);
+    let mut state = sampler.create_state();
+    let mut buffer = vec![0.0; 40];
+    sampler.fill_samples(&mut state, &mut buffer, 1.0 / 44100.0, 440.0, 1.0);
+    assert_eq!(&buffer[..32], &data[..]);
+    assert!(buffer[32..].iter().all(|x| *x == 0.0));
+}
+
+#[test]
+fn test_loops_are_continuous() {
+    // A cosine with a period of exactly the loop length, played at half speed.
+    let data = (0..64)
+        .map(|x| (2.0 * std::f32::consts::PI * x as f32 / 64.0).cos())
+        .collect::<Vec<_>>();
+    for mode in [LoopMode::Forward, LoopMode::PingPong] {
+        for interpolation in [
+            Interpolation::Linear,
+            Interpolation::Cubic,
+            Interpolation::Sinc,
+        ] {
+            let sampler = SamplerOscillator::new(data.clone(), 44100.0, 440.0)
+                .with_loop(mode, 0, 64)
+                .with_interpolation(interpolation);
+            let mut state = sampler.create_state();
+            let mut buffer = vec![0.0; 600];
+            sampler.fill_samples(&mut state, &mut buffer, 1.0 / 44100.0, 220.0, 1.0);
+            // Skip the start, where sinc interpolation sees the silence before the data.
+            for pair in buffer[20..].windows(2) {
+                assert!(
+                    (pair[1] - pair[0]).abs() < 0.06,
+                    "{:?} {:?}: jump from {} to {}",
+                    mode,
+                    interpolation,
+                    pair[0],
+                    pair[1]
+                );
+            }
+        }
+    }
+}
@@ -1,4 +1,4 @@
-use super::Oscillator;
+use super::{blep::poly_blep, Oscillator};
 
 pub struct SawOscillator;
 
@@ -8,6 +8,31 @@ pub struct SawOscillatorState {
     phase: f32,
 }
 
+impl SawOscillatorState {
+    fn at(phase: f32) -> Self {
+        Self {
+            phase: phase.rem_euclid(1.0),
+        }
+    }
+
+    fn advance(&mut self, increment: f32) {
+        self.phase += increment;
+        self.phase %= 1.0;
+    }
+
+    fn next_naive(&mut self, increment: f32) -> f32 {
+        let out = 2.0 * self.phase - 1.0;
+        self.advance(increment);
+        out
+    }
+
+    fn next_poly_blep(&mut self, increment: f32) -> f32 {
+        let out = 2.0 * self.phase - 1.0 - poly_blep(self.phase, increment);
+        self.advance(increment);
+        out
+    }
+}
+
 impl Oscillator for SawOscillator {
     type State = SawOscillatorState;
 
@@ -15,6 +40,10 @@ impl Oscillator for SawOscillator {
         SawOscillatorState::default()
     }
 
+    fn create_state_at(&self, phase: f32) -> Self::State {
+        SawOscillatorState::at(phase)
+    }
+
     fn fill_samples(
         &self,
         state: &mut Self::State,
@@ -25,9 +54,65 @@ impl Oscillator for SawOscillator {
     ) {
         let increment = delta_t * freq;
         for sample in buffer.iter_mut() {
-            *sample += (2.0 * state.phase - 1.0) * amp;
-            state.phase += increment;
-            state.phase %= 1.0;
+            *sample += state.next_naive(increment) * amp;
+        }
+    }
+
+    fn fill_samples_modulated(
+        &self,
+        state: &mut Self::State,
+        buffer: &mut [f32],
+        delta_t: f32,
+        freqs: &[f32],
+        amps: &[f32],
+    ) {
+        for ((sample, freq), amp) in buffer.iter_mut().zip(freqs).zip(amps) {
+            *sample += state.next_naive(delta_t * freq) * amp;
+        }
+    }
+}
+
+/// A saw oscillator with PolyBLEP anti-aliasing applied to its falling edge.
+///
+/// Sounds the same as [`SawOscillator`] at low frequencies, but aliases much less in the upper
+/// octaves.
+pub struct PolyBlepSawOscillator;
+
+impl Oscillator for PolyBlepSawOscillator {
+    type State = SawOscillatorState;
+
+    fn create_state(&self) -> Self::State {
+        SawOscillatorState::default()
+    }
+
+    fn create_state_at(&self, phase: f32) -> Self::State {
+        SawOscillatorState::at(phase)
+    }
+
+    fn fill_samples(
+        &self,
+        state: &mut Self::State,
+        buffer: &mut [f32],
+        delta_t: f32,
+        freq: f32,
+        amp: f32,
+    ) {
+        let increment = delta_t * freq;
+        for sample in buffer.iter_mut() {
+            *sample += state.next_poly_blep(increment) * amp;
+        }
+    }
+
+    fn fill_samples_modulated(
+        &self,
+        state: &mut Self::State,
+        buffer: &mut [f32],
+        delta_t: f32,
+        freqs: &[f32],
+        amps: &[f32],
+    ) {
+        for ((sample, freq), amp) in buffer.iter_mut().zip(freqs).zip(amps) {
+            *sample += state.next_poly_blep(delta_t * freq) * amp;
         }
     }
 }
@@ -0,0 +1,439 @@
+//! Wrappers distorting the output of another oscillator, for West Coast style timbres.
+//!
+//! [`Waveshape`] passes the inner oscillator through a static transfer function and [`Wavefold`]
+//! folds it back on itself whenever it exceeds the unit range. Both generate harmonics that the
+//! inner oscillator does not have, which can alias. To tame this, the inner oscillator can be
+//! rendered at a multiple of the sample rate and low-pass filtered back down.
+//!
+//! The amplitude is applied after shaping, so the timbre does not depend on the note's
+//! amplitude. Use the drive to make it brighter instead.
+
+use std::f32::consts::PI;
+
+use super::Oscillator;
+
+/// The transfer function of a [`Waveshape`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum ShapingFunction {
+    /// `tanh` saturation, normalized so that an input of 1 still produces 1.
+    Tanh,
+    /// A weighted sum of Chebyshev polynomials of the first kind. Weight `i` belongs to `T(i + 1)`,
+    /// which turns a full-scale sine into its `i + 1`th harmonic. The input is clamped to the
+    /// unit range.
+    Chebyshev(Vec<f32>),
+}
+
+impl ShapingFunction {
+    fn apply(&self, x: f32, drive: f32) -> f32 {
+        match self {
+            ShapingFunction::Tanh => (x * drive).tanh() / drive.tanh().max(f32::EPSILON),
+            ShapingFunction::Chebyshev(weights) => {
+                let x = (x * drive).clamp(-1.0, 1.0);
+                let (mut prev, mut cur) = (1.0, x);
+                let mut out = 0.0;
+                for weight in weights {
+                    out += weight * cur;
+                    (prev, cur) = (cur, 2.0 * x * cur - prev);
+                }
+                out
+            }
+        }
+    }
+}
+
+/// An oscillator passed through a [`ShapingFunction`].
+#[derive(Debug, Clone)]
+pub struct Waveshape<O> {
+    pub inner: O,
+    pub function: ShapingFunction,
+    /// The gain applied to the inner oscillator before shaping.
+    pub drive: f32,
+    /// The factor by which the inner oscillator is oversampled. 1 disables oversampling.
+    pub oversampling: usize,
+}
+
+impl<O: Oscillator> Waveshape<O> {
+    pub fn new(inner: O, function: ShapingFunction) -> Self {
+        Self {
+            inner,
+            function,
+            drive: 1.0,
+            oversampling: 1,
+        }
+    }
+
+    pub fn with_drive(mut self, drive: f32) -> Self {
+        self.drive = drive;
+        self
+    }
+
+    pub fn with_oversampling(mut self, oversampling: usize) -> Self {
+        self.oversampling = oversampling;
+        self
+    }
+
+    fn shaping(&self) -> Shaping<'_, O, impl Fn(f32) -> f32 + '_> {
+        Shaping {
+            inner: &self.inner,
+            oversampling: self.oversampling,
+            shape: |x| self.function.apply(x, self.drive),
+        }
+    }
+}
+
+/// A multi-stage wavefolder.
+///
+/// Every stage is a sine folder, `sin(pi / 2 * gain * x)`, which passes small signals almost
+/// unchanged and folds larger ones back towards zero. The drive is spread evenly over the stages,
+/// so more stages fold more smoothly at the same drive.
+#[derive(Debug, Clone)]
+pub struct Wavefold<O> {
+    pub inner: O,
+    /// The number of folding stages in series.
+    pub stages: usize,
+    /// The total gain applied over all stages. Around 1, the inner oscillator passes through
+    /// with little change; every further unit of drive adds roughly one fold per half cycle.
+    pub drive: f32,
+    /// An offset added before folding, between -1 and 1. Non-zero values fold the positive and
+    /// negative halves differently, adding even harmonics.
+    pub symmetry: f32,
+    /// The factor by which the inner oscillator is oversampled. 1 disables oversampling.
+    pub oversampling: usize,
+}
+
+impl<O: Oscillator> Wavefold<O> {
+    pub fn new(inner: O, drive: f32) -> Self {
+        Self {
+            inner,
+            stages: 1,
+            drive,
+            symmetry: 0.0,
+            oversampling: 1,
+        }
+    }
+
+    pub fn with_stages(mut self, stages: usize) -> Self {
+        self.stages = stages;
+        self
+    }
+
+    pub fn with_symmetry(mut self, symmetry: f32) -> Self {
+        self.symmetry = symmetry;
+        self
+    }
+
+    pub fn with_oversampling(mut self, oversampling: usize) -> Self {
+        self.oversampling = oversampling;
+        self
+    }
+
+    fn fold(&self, x: f32) -> f32 {
+        let stages = self.stages.max(1);
+        let gain = self.drive.max(0.0).powf(1.0 / stages as f32);
+        (0..stages).fold(x, |y, _| (0.5 * PI * gain * y).sin())
+    }
+
+    fn shaping(&self) -> Shaping<'_, O, impl Fn(f32) -> f32 + '_> {
+        // Remove the offset the symmetry adds to silence, so that silence stays silent.
+        let rest = self.fold(self.symmetry);
+        Shaping {
+            inner: &self.inner,
+            oversampling: self.oversampling,
+            shape: move |x| self.fold(x + self.symmetry) - rest,
+        }
+    }
+}
+
+/// The taps of a Blackman-windowed sinc low-pass filter for decimating by `factor`.
+fn decimation_filter(factor: usize) -> Vec<f32> {
+    if factor <= 1 {
+        return vec![1.0];
+    }
+    let half = 8 * factor;
+    let cutoff = 0.45 / factor as f32;
+    let mut taps = (0..=2 * half)
+        .map(|i| {
+            let t = i as f32 - half as f32;
+            let sinc = if t == 0.0 {
+                2.0 * cutoff
+            } else {
+                (2.0 * PI * cutoff * t).sin() / (PI * t)
+            };
+            let w = 2.0 * PI * i as f32 / (2 * half) as f32;
+            let window = 0.42 - 0.5 * w.cos() + 0.08 * (2.0 * w).cos();
+            sinc * window
+        })
+        .collect::<Vec<_>>();
+    let sum = taps.iter().sum::<f32>();
+    taps.iter_mut().for_each(|tap| *tap /= sum);
+    taps
+}
+
+/// What a [`ShaperState`] renders: the inner oscillator, the factor by which it is oversampled
+/// and the function shaping it.
+struct Shaping<'a, O, F> {
+    inner: &'a O,
+    oversampling: usize,
+    shape: F,
+}
+
+/// Low-pass filters an oversampled signal and decimates it back to the sample rate.
+#[derive(Debug, Clone, Default)]
+struct Decimator {
+    /// The shaped, oversampled signal. Between buffers, it keeps the samples the filter still
+    /// needs.
+    oversampled: Vec<f32>,
+    /// The filter taps, built on first use, and the factor they were built for.
+    filter: Vec<f32>,
+    factor: usize,
+}
+
+impl Decimator {
+    /// Pass the signal from `fill`, which adds it at `factor` times the sample rate to the buffer
+    /// it is given, through `shape` and add it to `buffer` after decimating. `amp(i)` is the
+    /// amplitude of output sample `i`.
+    fn render(
+        &mut self,
+        factor: usize,
+        buffer: &mut [f32],
+        fill: impl FnOnce(&mut [f32]),
+        amp: impl Fn(usize) -> f32,
+        shape: impl Fn(f32) -> f32,
+    ) {
+        if self.factor != factor {
+            self.filter = decimation_filter(factor);
+            self.factor = factor;
+        }
+        let filter = &self.filter;
+        let history = filter.len() - 1;
+
+        self.oversampled.resize(history, 0.0);
+        self.oversampled
+            .resize(history + buffer.len() * factor, 0.0);
+        let fresh = &mut self.oversampled[history..];
+        fill(fresh);
+        fresh.iter_mut().for_each(|x| *x = shape(*x));
+
+        for (i, sample) in buffer.iter_mut().enumerate() {
+            let end = filter.len() + i * factor;
+            let window = &self.oversampled[end - filter.len()..end];
+            let out = window.iter().zip(filter).map(|(x, h)| x * h).sum::<f32>();
+            *sample += out * amp(i);
+        }
+        self.oversampled.drain(..buffer.len() * factor);
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ShaperState<S> {
+    inner: S,
+    decimator: Decimator,
+    /// Scratch space for the oversampled frequencies and the unit amplitudes of the inner
+    /// oscillator in the modulated path.
+    freqs: Vec<f32>,
+    unit_amps: Vec<f32>,
+}
+
+impl<S> ShaperState<S> {
+    fn new(inner: S) -> Self {
+        Self {
+            inner,
+            decimator: Decimator::default(),
+            freqs: vec![],
+            unit_amps: vec![],
+        }
+    }
+
+    /// Render the inner oscillator at `shaping.oversampling` times the sample rate, shape it and
+    /// add it to `buffer` after decimating.
+    fn render<O: Oscillator<State = S>>(
+        &mut self,
+        shaping: Shaping<O, impl Fn(f32) -> f32>,
+        buffer: &mut [f32],
+        delta_t: f32,
+        freq: f32,
+        amp: f32,
+    ) {
+        let factor = shaping.oversampling.max(1);
+        let inner = &mut self.inner;
+        self.decimator.render(
+            factor,
+            buffer,
+            |fresh| {
+                let delta_t = delta_t / factor as f32;
+                shaping.inner.fill_samples(inner, fresh, delta_t, freq, 1.0);
+            },
+            |_| amp,
+            shaping.shape,
+        );
+    }
+
+    /// Like [`ShaperState::render`], with a frequency and amplitude for every sample.
+    fn render_modulated<O: Oscillator<State = S>>(
+        &mut self,
+        shaping: Shaping<O, impl Fn(f32) -> f32>,
+        buffer: &mut [f32],
+        delta_t: f32,
+        freqs: &[f32],
+        amps: &[f32],
+    ) {
+        let factor = shaping.oversampling.max(1);
+        let len = buffer.len() * factor;
+        self.freqs.resize(len, 0.0);
+        for (chunk, freq) in self.freqs.chunks_mut(factor).zip(freqs) {
+            chunk.fill(*freq);
+        }
+        self.unit_amps.resize(len, 1.0);
+        let (inner, inner_freqs, unit_amps) = (&mut self.inner, &self.freqs, &self.unit_amps);
+        self.decimator.render(
+            factor,
+            buffer,
+            |fresh| {
+                let delta_t = delta_t / factor as f32;
+                shaping
+                    .inner
+                    .fill_samples_modulated(inner, fresh, delta_t, inner_freqs, unit_amps);
+            },
+            |i| amps[i],
+            shaping.shape,
+        );
+    }
+}
+
+impl<O: Oscillator> Oscillator for Waveshape<O> {
+    type State = ShaperState<O::State>;
+
+    fn create_state(&self) -> Self::State {
+        ShaperState::new(self.inner.create_state())
+    }
+
+    fn create_state_at(&self, phase: f32) -> Self::State {
+        ShaperState::new(self.inner.create_state_at(phase))
+    }
+
+    fn reset_phase(&self, state: &mut Self::State, phase: f32) {
+        self.inner.reset_phase(&mut state.inner, phase);
+    }
+
+    fn fill_samples(
+        &self,
+        state: &mut Self::State,
+        buffer: &mut [f32],
+        delta_t: f32,
+        freq: f32,
+        amp: f32,
+    ) {
+        state.render(self.shaping(), buffer, delta_t, freq, amp);
+    }
+
+    fn fill_samples_modulated(
+        &self,
+        state: &mut Self::State,
+        buffer: &mut [f32],
+        delta_t: f32,
+        freqs: &[f32],
+        amps: &[f32],
+    ) {
+        state.render_modulated(self.shaping(), buffer, delta_t, freqs, amps);
+    }
+}
+
+impl<O: Oscillator> Oscillator for Wavefold<O> {
+    type State = ShaperState<O::State>;
+
+    fn create_state(&self) -> Self::State {
+        ShaperState::new(self.inner.create_state())
+    }
+
+    fn create_state_at(&self, phase: f32) -> Self::State {
+        ShaperState::new(self.inner.create_state_at(phase))
+    }
+
+    fn reset_phase(&self, state: &mut Self::State, phase: f32) {
+        self.inner.reset_phase(&mut state.inner, phase);
+    }
+
+    fn fill_samples(
+        &self,
+        state: &mut Self::State,
+        buffer: &mut [f32],
+        delta_t: f32,
+        freq: f32,
+        amp: f32,
+    ) {
+        state.render(self.shaping(), buffer, delta_t, freq, amp);
+    }
+
+    fn fill_samples_modulated(
+        &self,
+        state: &mut Self::State,
+        buffer: &mut [f32],
+        delta_t: f32,
+        freqs: &[f32],
+        amps: &[f32],
+    ) {
+        state.render_modulated(self.shaping(), buffer, delta_t, freqs, amps);
+    }
+}
+
+#[test]
+fn test_chebyshev_produces_harmonic() {
+    // T3 turns a sine into a sine at three times the frequency (with the sign flipped).
+    use super::sine::SineOscillator;
+
+    let shaper = Waveshape::new(
+        SineOscillator::default(),
+        ShapingFunction::Chebyshev(vec![0.0, 0.0, 1.0]),
+    );
+    let mut shaped = vec![0.0; 256];
+    let mut reference = vec![0.0; 256];
+    shaper.fill_samples(
+        &mut shaper.create_state(),
+        &mut shaped,
+        1.0 / 44100.0,
+        100.0,
+        1.0,
+    );
+    SineOscillator::default().fill_samples(
+        &mut SineOscillator::default().create_state(),
+        &mut reference,
+        1.0 / 44100.0,
+        300.0,
+        -1.0,
+    );
+    for (a, b) in shaped.iter().zip(&reference) {
+        assert!((a - b).abs() < 1e-3, "{} != {}", a, b);
+    }
+}
+
+#[test]
+fn test_oversampled_modulated_fill_matches_scalar() {
+    use super::sine::SineOscillator;
+
+    let folder = Wavefold::new(SineOscillator::default(), 3.0).with_oversampling(4);
+    let delta_t = 1.0 / 44100.0;
+    let mut scalar = vec![0.0; 256];
+    let mut modulated = vec![0.0; 256];
+    let mut state = folder.create_state();
+    // Two buffers, so that the filter history carries over.
+    folder.fill_samples(&mut state, &mut scalar[..128], delta_t, 440.0, 0.5);
+    folder.fill_samples(&mut state, &mut scalar[128..], delta_t, 440.0, 0.5);
+    let mut state = folder.create_state();
+    folder.fill_samples_modulated(
+        &mut state,
+        &mut modulated[..128],
+        delta_t,
+        &[440.0; 128],
+        &[0.5; 128],
+    );
+    folder.fill_samples_modulated(
+        &mut state,
+        &mut modulated[128..],
+        delta_t,
+        &[440.0; 128],
+        &[0.5; 128],
+    );
+    for (a, b) in scalar.iter().zip(&modulated) {
+        assert!((a - b).abs() < 1e-5, "{} != {}", a, b);
+    }
+}
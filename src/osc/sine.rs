@@ -1,7 +1,90 @@
-use super::Oscillator;
+use std::f32::consts::TAU;
+
+use super::{
+    fast_sin::{sin_cycles_poly, sin_cycles_table, sine_table, LANES},
+    Oscillator,
+};
+
+/// How a sine oscillator computes `sin`.
+///
+/// The error bounds below are the largest absolute errors measured against a double precision
+/// `sin` over several cycles. For comparison, `f32::sin` of a single precision phase is off by
+/// up to 2.5e-7.
+///
+/// The timings are for rendering 200,000 buffers of 256 samples in a release build for x86-64
+/// without extra target features. They depend on the target and the compiler; measure on yours
+/// before relying on them.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SineApproximation {
+    /// Call `f32::sin` for every sample. Took 215-240 ms.
+    #[default]
+    Exact,
+    /// An 11th order polynomial evaluated in chunks of 8 samples, written so that the compiler
+    /// can auto-vectorize it. Took 110-115 ms. Error below 4e-7.
+    Polynomial,
+    /// Linear interpolation in a shared table of 4096 points per cycle. The lookups are not
+    /// vectorized. Took 195-200 ms. Error below 6e-7.
+    Table,
+}
 
 #[derive(Debug, Clone, Default)]
-pub struct SineOscillator;
+pub struct SineOscillator {
+    pub approximation: SineApproximation,
+}
+
+impl SineOscillator {
+    pub fn new(approximation: SineApproximation) -> Self {
+        Self { approximation }
+    }
+
+    /// Render samples, with `step` as in [`SineOscillatorState::render`].
+    fn render(
+        &self,
+        state: &mut SineOscillatorState,
+        buffer: &mut [f32],
+        step: impl FnMut(usize) -> (f32, f32),
+    ) {
+        match self.approximation {
+            SineApproximation::Exact => state.render(buffer, step, f32::sin),
+            SineApproximation::Polynomial => render_chunked(state, buffer, step, sin_cycles_poly),
+            SineApproximation::Table => {
+                let table = sine_table();
+                render_chunked(state, buffer, step, |t| sin_cycles_table(table, t));
+            }
+        }
+    }
+}
+
+/// Render samples in chunks of [`LANES`], so that `approx` (taking the phase in cycles) is
+/// evaluated for a whole chunk at once and can be vectorized.
+fn render_chunked(
+    state: &mut SineOscillatorState,
+    buffer: &mut [f32],
+    mut step: impl FnMut(usize) -> (f32, f32),
+    approx: impl Fn(f32) -> f32,
+) {
+    for (chunk_ix, chunk) in buffer.chunks_mut(LANES).enumerate() {
+        // Phase accumulation is sequential, but evaluating the approximation is not. The
+        // approximations reduce the phase themselves, so it only needs wrapping once per chunk.
+        let mut phase = state.phase;
+        let mut amps = [0.0; LANES];
+        let phases: [f32; LANES] = std::array::from_fn(|lane| {
+            let current = phase;
+            if lane < chunk.len() {
+                let (increment, amp) = step(chunk_ix * LANES + lane);
+                amps[lane] = amp;
+                phase += increment;
+            }
+            current
+        });
+        state.phase = wrap_phase(phase);
+        let out: [f32; LANES] =
+            std::array::from_fn(|lane| approx(phases[lane] * (1.0 / TAU)) * amps[lane]);
+        for (sample, out) in chunk.iter_mut().zip(&out) {
+            *sample += out;
+        }
+    }
+}
 
 #[derive(Debug, Clone, Default)]
 pub struct SineOscillatorState {
@@ -9,6 +92,65 @@ pub struct SineOscillatorState {
     phase: f32,
 }
 
+impl SineOscillatorState {
+    /// Create a state starting at `phase`, between 0 and 1.
+    pub(crate) fn at(phase: f32) -> Self {
+        Self {
+            phase: phase.rem_euclid(1.0) * 2.0 * std::f32::consts::PI,
+        }
+    }
+
+    /// Advance the phase by `increment` radians, returning the phase before advancing.
+    pub(crate) fn advance(&mut self, increment: f32) -> f32 {
+        let phase = self.phase;
+        self.phase = wrap_phase(self.phase + increment);
+        phase
+    }
+
+    /// Add `waveform(phase)` to every sample, with the phase in radians. `step(i)` gives the
+    /// phase increment in radians and the amplitude of sample `i`; see [`constant_step`] and
+    /// [`modulated_step`].
+    pub(crate) fn render(
+        &mut self,
+        buffer: &mut [f32],
+        mut step: impl FnMut(usize) -> (f32, f32),
+        mut waveform: impl FnMut(f32) -> f32,
+    ) {
+        for (i, sample) in buffer.iter_mut().enumerate() {
+            let (increment, amp) = step(i);
+            *sample += waveform(self.advance(increment)) * amp;
+        }
+    }
+}
+
+/// The steps of [`SineOscillatorState::render`] for [`Oscillator::fill_samples`].
+pub(crate) fn constant_step(delta_t: f32, freq: f32, amp: f32) -> impl FnMut(usize) -> (f32, f32) {
+    let increment = TAU * freq * delta_t;
+    move |_| (increment, amp)
+}
+
+/// The steps of [`SineOscillatorState::render`] for [`Oscillator::fill_samples_modulated`].
+pub(crate) fn modulated_step<'a>(
+    delta_t: f32,
+    freqs: &'a [f32],
+    amps: &'a [f32],
+) -> impl FnMut(usize) -> (f32, f32) + 'a {
+    let scale = TAU * delta_t;
+    move |i| (scale * freqs[i], amps[i])
+}
+
+/// Wrap a phase in radians into `0..2pi`. Cheaper than `rem_euclid` for phases that are at most
+/// one cycle out of range, as they are after a single step.
+fn wrap_phase(phase: f32) -> f32 {
+    if (0.0..TAU).contains(&phase) {
+        phase
+    } else if (TAU..2.0 * TAU).contains(&phase) {
+        phase - TAU
+    } else {
+        phase.rem_euclid(TAU)
+    }
+}
+
 impl Oscillator for SineOscillator {
     type State = SineOscillatorState;
 
@@ -16,6 +158,10 @@ impl Oscillator for SineOscillator {
         SineOscillatorState::default()
     }
 
+    fn create_state_at(&self, phase: f32) -> Self::State {
+        SineOscillatorState::at(phase)
+    }
+
     fn fill_samples(
         &self,
         state: &mut Self::State,
@@ -24,11 +170,17 @@ impl Oscillator for SineOscillator {
         freq: f32,
         amp: f32,
     ) {
-        let increment = 2.0 * std::f32::consts::PI * freq * delta_t;
-        for sample in buffer.iter_mut() {
-            *sample += state.phase.sin() * amp;
-            state.phase += increment;
-            state.phase %= 2.0 * std::f32::consts::PI;
-        }
+        self.render(state, buffer, constant_step(delta_t, freq, amp));
+    }
+
+    fn fill_samples_modulated(
+        &self,
+        state: &mut Self::State,
+        buffer: &mut [f32],
+        delta_t: f32,
+        freqs: &[f32],
+        amps: &[f32],
+    ) {
+        self.render(state, buffer, modulated_step(delta_t, freqs, amps));
     }
 }
@@ -1,11 +1,115 @@
-use super::Oscillator;
+use super::{blep::poly_blep, Oscillator};
 
-pub struct SquareOscillator;
+/// A pulse oscillator. With the default pulse width of 0.5 this is a square wave.
+#[derive(Debug, Clone)]
+pub struct SquareOscillator {
+    /// The fraction of every cycle spent at the high level, between 0 and 1.
+    pub pulse_width: f32,
+    /// Optional LFO modulating the pulse width.
+    pub pwm: Option<PulseWidthModulation>,
+}
+
+/// A sine LFO applied to the pulse width of a pulse oscillator.
+#[derive(Debug, Clone)]
+pub struct PulseWidthModulation {
+    /// The LFO frequency. In Hz, or a multiple of the note frequency if `key_tracking` is set.
+    pub rate: f32,
+    /// How far the pulse width swings away from its base value in either direction.
+    pub depth: f32,
+    /// Whether `rate` is relative to the note frequency, which gives audio-rate PWM that follows
+    /// the pitch of the note.
+    pub key_tracking: bool,
+}
+
+impl PulseWidthModulation {
+    /// An LFO running at a fixed rate in Hz.
+    pub fn lfo(rate: f32, depth: f32) -> Self {
+        Self {
+            rate,
+            depth,
+            key_tracking: false,
+        }
+    }
+
+    /// A modulator running at a multiple of the note frequency.
+    pub fn audio_rate(ratio: f32, depth: f32) -> Self {
+        Self {
+            rate: ratio,
+            depth,
+            key_tracking: true,
+        }
+    }
+}
+
+impl SquareOscillator {
+    pub fn new(pulse_width: f32) -> Self {
+        Self {
+            pulse_width,
+            pwm: None,
+        }
+    }
+
+    pub fn with_pwm(mut self, pwm: PulseWidthModulation) -> Self {
+        self.pwm = Some(pwm);
+        self
+    }
+
+    fn next(&self, state: &mut SquareOscillatorState, delta_t: f32, freq: f32) -> f32 {
+        let width = next_pulse_width(self.pulse_width, self.pwm.as_ref(), state, delta_t, freq);
+        let out = if state.phase < width { 1.0 } else { -1.0 };
+        state.advance(freq * delta_t);
+        out
+    }
+}
+
+impl Default for SquareOscillator {
+    fn default() -> Self {
+        Self::new(0.5)
+    }
+}
 
 #[derive(Debug, Clone, Default)]
 pub struct SquareOscillatorState {
     /// A phase between 0 and 1.
     phase: f32,
+    /// The phase of the pulse width LFO, between 0 and 1.
+    pwm_phase: f32,
+}
+
+impl SquareOscillatorState {
+    fn at(phase: f32) -> Self {
+        Self {
+            phase: phase.rem_euclid(1.0),
+            pwm_phase: 0.0,
+        }
+    }
+
+    fn advance(&mut self, increment: f32) {
+        self.phase += increment;
+        self.phase %= 1.0;
+    }
+}
+
+/// Get the pulse width for the current sample and advance the PWM LFO.
+fn next_pulse_width(
+    pulse_width: f32,
+    pwm: Option<&PulseWidthModulation>,
+    state: &mut SquareOscillatorState,
+    delta_t: f32,
+    freq: f32,
+) -> f32 {
+    let Some(pwm) = pwm else {
+        return pulse_width.clamp(0.0, 1.0);
+    };
+    let lfo = (2.0 * std::f32::consts::PI * state.pwm_phase).sin();
+    let rate = if pwm.key_tracking {
+        pwm.rate * freq
+    } else {
+        pwm.rate
+    };
+    state.pwm_phase += rate * delta_t;
+    state.pwm_phase %= 1.0;
+    (pulse_width + lfo * pwm.depth).clamp(0.0, 1.0)
 }
 
 impl Oscillator for SquareOscillator {
@@ -15,6 +119,10 @@ impl Oscillator for SquareOscillator {
         SquareOscillatorState::default()
     }
 
+    fn create_state_at(&self, phase: f32) -> Self::State {
+        SquareOscillatorState::at(phase)
+    }
+
     fn fill_samples(
         &self,
         state: &mut Self::State,
@@ -23,11 +131,189 @@ impl Oscillator for SquareOscillator {
         freq: f32,
         amp: f32,
     ) {
+        for sample in buffer.iter_mut() {
+            *sample += self.next(state, delta_t, freq) * amp;
+        }
+    }
+
+    fn fill_samples_modulated(
+        &self,
+        state: &mut Self::State,
+        buffer: &mut [f32],
+        delta_t: f32,
+        freqs: &[f32],
+        amps: &[f32],
+    ) {
+        for ((sample, freq), amp) in buffer.iter_mut().zip(freqs).zip(amps) {
+            *sample += self.next(state, delta_t, *freq) * amp;
+        }
+    }
+}
+
+/// A pulse oscillator with PolyBLEP anti-aliasing applied to both of its edges.
+///
+/// Sounds the same as [`SquareOscillator`] at low frequencies, but aliases much less in the
+/// upper octaves.
+#[derive(Debug, Clone)]
+pub struct PolyBlepSquareOscillator {
+    /// The fraction of every cycle spent at the high level, between 0 and 1.
+    pub pulse_width: f32,
+    /// Optional LFO modulating the pulse width.
+    pub pwm: Option<PulseWidthModulation>,
+}
+
+impl PolyBlepSquareOscillator {
+    pub fn new(pulse_width: f32) -> Self {
+        Self {
+            pulse_width,
+            pwm: None,
+        }
+    }
+
+    pub fn with_pwm(mut self, pwm: PulseWidthModulation) -> Self {
+        self.pwm = Some(pwm);
+        self
+    }
+
+    fn next(&self, state: &mut SquareOscillatorState, delta_t: f32, freq: f32) -> f32 {
         let increment = freq * delta_t;
+        let width = next_pulse_width(self.pulse_width, self.pwm.as_ref(), state, delta_t, freq);
+        let naive = if state.phase < width { 1.0 } else { -1.0 };
+        let out = naive + poly_blep(state.phase, increment)
+            - poly_blep((state.phase + 1.0 - width) % 1.0, increment);
+        state.advance(increment);
+        out
+    }
+}
+
+impl Default for PolyBlepSquareOscillator {
+    fn default() -> Self {
+        Self::new(0.5)
+    }
+}
+
+impl Oscillator for PolyBlepSquareOscillator {
+    type State = SquareOscillatorState;
+
+    fn create_state(&self) -> Self::State {
+        SquareOscillatorState::default()
+    }
+
+    fn create_state_at(&self, phase: f32) -> Self::State {
+        SquareOscillatorState::at(phase)
+    }
+
+    fn fill_samples(
+        &self,
+        state: &mut Self::State,
+        buffer: &mut [f32],
+        delta_t: f32,
+        freq: f32,
+        amp: f32,
+    ) {
         for sample in buffer.iter_mut() {
-            *sample += if state.phase < 0.5 { amp } else { -amp };
-            state.phase += increment;
-            state.phase %= 1.0;
+            *sample += self.next(state, delta_t, freq) * amp;
+        }
+    }
+
+    fn fill_samples_modulated(
+        &self,
+        state: &mut Self::State,
+        buffer: &mut [f32],
+        delta_t: f32,
+        freqs: &[f32],
+        amps: &[f32],
+    ) {
+        for ((sample, freq), amp) in buffer.iter_mut().zip(freqs).zip(amps) {
+            *sample += self.next(state, delta_t, *freq) * amp;
+        }
+    }
+}
+
+/// Render one second at 441 Hz, so that every cycle is exactly 100 samples long.
+#[cfg(test)]
+fn render<O: Oscillator>(osc: &O) -> Vec<f32> {
+    let mut buf = vec![0.0; 44100];
+    osc.fill_samples(&mut osc.create_state(), &mut buf, 1.0 / 44100.0, 441.0, 1.0);
+    buf
+}
+
+/// The fraction of the cycle starting at sample `start` spent at the high level.
+#[cfg(test)]
+fn duty_cycle(samples: &[f32], start: usize) -> f32 {
+    samples[start..start + 100]
+        .iter()
+        .filter(|x| **x > 0.0)
+        .count() as f32
+        / 100.0
+}
+
+#[test]
+fn test_duty_cycle_follows_pulse_width() {
+    for width in [0.1, 0.25, 0.5, 0.8] {
+        for out in [
+            render(&SquareOscillator::new(width)),
+            render(&PolyBlepSquareOscillator::new(width)),
+        ] {
+            // The mean of a pulse between -1 and 1 gives its duty cycle exactly, even with the
+            // PolyBLEP edges.
+            let mean = out.iter().sum::<f32>() / out.len() as f32;
+            assert!(
+                ((mean + 1.0) / 2.0 - width).abs() < 1e-3,
+                "{} != {}",
+                mean,
+                width
+            );
+            assert!((duty_cycle(&out, 1000) - width).abs() < 0.02);
+        }
+    }
+}
+
+#[test]
+fn test_pulse_width_is_clamped() {
+    for (width, level) in [(-0.5, -1.0), (0.0, -1.0), (1.0, 1.0), (1.5, 1.0)] {
+        for out in [
+            render(&SquareOscillator::new(width)),
+            render(&PolyBlepSquareOscillator::new(width)),
+        ] {
+            for x in out {
+                assert!(
+                    (x - level).abs() < 1e-3,
+                    "{} != {} at width {}",
+                    x,
+                    level,
+                    width
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn test_lfo_moves_duty_cycle() {
+    // A 5 Hz LFO peaks after 50 ms and bottoms out after 150 ms.
+    let pwm = PulseWidthModulation::lfo(5.0, 0.3);
+    for out in [
+        render(&SquareOscillator::default().with_pwm(pwm.clone())),
+        render(&PolyBlepSquareOscillator::default().with_pwm(pwm.clone())),
+    ] {
+        assert!((duty_cycle(&out, 2150) - 0.8).abs() < 0.02);
+        assert!((duty_cycle(&out, 6565) - 0.2).abs() < 0.02);
+    }
+}
+
+#[test]
+fn test_audio_rate_pwm_moves_duty_cycle() {
+    // At half the note frequency, the pulse width is above 0.5 in even cycles and below in odd
+    // ones. The edge is where the phase meets the moving width, around 0.73 and 0.27.
+    let pwm = PulseWidthModulation::audio_rate(0.5, 0.3);
+    for out in [
+        render(&SquareOscillator::default().with_pwm(pwm.clone())),
+        render(&PolyBlepSquareOscillator::default().with_pwm(pwm.clone())),
+    ] {
+        for cycle in 0..20 {
+            let expected = if cycle % 2 == 0 { 0.73 } else { 0.27 };
+            assert!((duty_cycle(&out, cycle * 100) - expected).abs() < 0.02);
         }
     }
 }
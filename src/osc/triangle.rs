@@ -0,0 +1,178 @@
+use super::{blep::poly_blep, Oscillator};
+
+pub struct TriangleOscillator;
+
+#[derive(Debug, Clone)]
+pub struct TriangleOscillatorState {
+    /// A phase between 0 and 1.
+    phase: f32,
+    /// The integrator output used by the band-limited variant, before scaling.
+    integrator: f32,
+}
+
+impl TriangleOscillatorState {
+    fn at(phase: f32) -> Self {
+        let phase = phase.rem_euclid(1.0);
+        Self {
+            phase,
+            // Start the integrator where the naive triangle would be at this phase.
+            integrator: naive_triangle(phase) * 0.25,
+        }
+    }
+
+    fn advance(&mut self, increment: f32) {
+        self.phase += increment;
+        self.phase %= 1.0;
+    }
+
+    fn next_naive(&mut self, increment: f32) -> f32 {
+        let out = naive_triangle(self.phase);
+        self.advance(increment);
+        out
+    }
+
+    fn next_poly_blep(&mut self, increment: f32) -> f32 {
+        let before = self.integrator;
+        let naive = if self.phase < 0.5 { 1.0 } else { -1.0 };
+        let square = naive + poly_blep(self.phase, increment)
+            - poly_blep((self.phase + 0.5) % 1.0, increment);
+        self.integrator += increment * square;
+        self.advance(increment);
+        // The PolyBLEP square is centered on its samples, so the integrator lags the naive
+        // triangle by half a sample. Away from the corners it follows it exactly, so pulling
+        // towards it removes drift without bending the ramps like a leak towards zero would.
+        let target = naive_triangle((self.phase - 0.5 * increment).rem_euclid(1.0)) * 0.25;
+        self.integrator += INTEGRATOR_CORRECTION * (target - self.integrator);
+        // Averaging over the step makes up for the lag.
+        2.0 * (before + self.integrator)
+    }
+}
+
+/// How much of the difference to the naive triangle the band-limited integrator makes up every
+/// sample.
+const INTEGRATOR_CORRECTION: f32 = 0.001;
+
+impl Default for TriangleOscillatorState {
+    fn default() -> Self {
+        Self::at(0.0)
+    }
+}
+
+fn naive_triangle(phase: f32) -> f32 {
+    1.0 - 4.0 * (phase - 0.5).abs()
+}
+
+impl Oscillator for TriangleOscillator {
+    type State = TriangleOscillatorState;
+
+    fn create_state(&self) -> Self::State {
+        TriangleOscillatorState::default()
+    }
+
+    fn create_state_at(&self, phase: f32) -> Self::State {
+        TriangleOscillatorState::at(phase)
+    }
+
+    fn fill_samples(
+        &self,
+        state: &mut Self::State,
+        buffer: &mut [f32],
+        delta_t: f32,
+        freq: f32,
+        amp: f32,
+    ) {
+        let increment = freq * delta_t;
+        for sample in buffer.iter_mut() {
+            *sample += state.next_naive(increment) * amp;
+        }
+    }
+
+    fn fill_samples_modulated(
+        &self,
+        state: &mut Self::State,
+        buffer: &mut [f32],
+        delta_t: f32,
+        freqs: &[f32],
+        amps: &[f32],
+    ) {
+        for ((sample, freq), amp) in buffer.iter_mut().zip(freqs).zip(amps) {
+            *sample += state.next_naive(freq * delta_t) * amp;
+        }
+    }
+}
+
+/// A triangle oscillator made by integrating a PolyBLEP square wave.
+///
+/// The integrator is pulled slowly towards the naive triangle, so that offsets introduced by
+/// frequency changes die out within a few tens of milliseconds.
+pub struct PolyBlepTriangleOscillator;
+
+impl Oscillator for PolyBlepTriangleOscillator {
+    type State = TriangleOscillatorState;
+
+    fn create_state(&self) -> Self::State {
+        TriangleOscillatorState::default()
+    }
+
+    fn create_state_at(&self, phase: f32) -> Self::State {
+        TriangleOscillatorState::at(phase)
+    }
+
+    fn fill_samples(
+        &self,
+        state: &mut Self::State,
+        buffer: &mut [f32],
+        delta_t: f32,
+        freq: f32,
+        amp: f32,
+    ) {
+        let increment = freq * delta_t;
+        for sample in buffer.iter_mut() {
+            *sample += state.next_poly_blep(increment) * amp;
+        }
+    }
+
+    fn fill_samples_modulated(
+        &self,
+        state: &mut Self::State,
+        buffer: &mut [f32],
+        delta_t: f32,
+        freqs: &[f32],
+        amps: &[f32],
+    ) {
+        for ((sample, freq), amp) in buffer.iter_mut().zip(freqs).zip(amps) {
+            *sample += state.next_poly_blep(freq * delta_t) * amp;
+        }
+    }
+}
+
+#[test]
+fn test_poly_blep_matches_naive_at_low_frequency() {
+    // The band-limited corners are rounded, but the ramps between them should be straight. The
+    // first 100 ms are skipped while the integrator settles from its starting value.
+    for freq in [20.0, 220.0] {
+        let increment = freq / 44100.0;
+        let mut naive = vec![0.0; 44100];
+        let mut poly_blep = vec![0.0; 44100];
+        TriangleOscillator.fill_samples(
+            &mut TriangleOscillator.create_state(),
+            &mut naive,
+            1.0 / 44100.0,
+            freq,
+            1.0,
+        );
+        PolyBlepTriangleOscillator.fill_samples(
+            &mut PolyBlepTriangleOscillator.create_state(),
+            &mut poly_blep,
+            1.0 / 44100.0,
+            freq,
+            1.0,
+        );
+        for (ix, (a, b)) in naive.iter().zip(&poly_blep).enumerate().skip(4410) {
+            let corner_distance = ((ix as f32 * increment + 0.25) % 0.5 - 0.25).abs();
+            if corner_distance > 2.0 * increment {
+                assert!((a - b).abs() < 1e-3, "{} != {} at {} Hz", a, b, freq);
+            }
+        }
+    }
+}
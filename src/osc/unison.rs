@@ -0,0 +1,223 @@
+//! Unison: several detuned copies of an oscillator played at once, e.g. the "supersaw".
+
+use rand::Rng;
+
+use super::{noise::NoteRngs, Oscillator};
+
+/// Plays `voices` copies of the inner oscillator, spread evenly across `detune` cents around
+/// the note frequency.
+#[derive(Debug, Clone)]
+pub struct Unison<O> {
+    /// The oscillator every voice plays.
+    pub inner: O,
+    /// The number of voices.
+    pub voices: usize,
+    /// The distance between the lowest and the highest voice, in cents.
+    pub detune: f32,
+    /// How much the starting phase of every voice is randomized, between 0 (all voices start in
+    /// phase) and 1 (fully random phases).
+    pub phase_randomness: f32,
+    /// Whether to scale the output by `1 / sqrt(voices)` so that the loudness stays roughly the
+    /// same regardless of the voice count.
+    pub compensate_amplitude: bool,
+    /// The seed of the random number generator for the phase offsets. As with
+    /// [`NoiseOscillator::seed`](super::noise::NoiseOscillator::seed), every note gets its own
+    /// stream, so that seeded notes are reproducible but still differ from each other.
+    pub seed: Option<u64>,
+    rngs: NoteRngs,
+}
+
+impl<O: Oscillator> Unison<O> {
+    pub fn new(inner: O, voices: usize, detune: f32) -> Self {
+        Self {
+            inner,
+            voices,
+            detune,
+            phase_randomness: 1.0,
+            compensate_amplitude: true,
+            seed: None,
+            rngs: NoteRngs::default(),
+        }
+    }
+
+    pub fn with_phase_randomness(mut self, phase_randomness: f32) -> Self {
+        self.phase_randomness = phase_randomness;
+        self
+    }
+
+    pub fn with_amplitude_compensation(mut self, compensate_amplitude: bool) -> Self {
+        self.compensate_amplitude = compensate_amplitude;
+        self
+    }
+
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    /// The frequency multiplier of voice `ix`.
+    fn voice_ratio(&self, ix: usize) -> f32 {
+        if self.voices < 2 {
+            return 1.0;
+        }
+        let spread = ix as f32 / (self.voices - 1) as f32 - 0.5;
+        2.0_f32.powf(self.detune * spread / 1200.0)
+    }
+
+    fn voice_amp(&self) -> f32 {
+        if self.compensate_amplitude && self.voices > 0 {
+            1.0 / (self.voices as f32).sqrt()
+        } else {
+            1.0
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct UnisonState<S> {
+    voices: Vec<S>,
+    /// The random phase offset of every voice, kept so that resetting the phase is deterministic.
+    offsets: Vec<f32>,
+    /// Scratch space for the frequencies and amplitudes of a voice in the modulated path.
+    freqs: Vec<f32>,
+    amps: Vec<f32>,
+}
+
+impl<O: Oscillator> Oscillator for Unison<O> {
+    type State = UnisonState<O::State>;
+
+    fn create_state(&self) -> Self::State {
+        self.create_state_at(0.0)
+    }
+
+    fn create_state_at(&self, phase: f32) -> Self::State {
+        let mut rng = self.rngs.next(self.seed);
+        let offsets = (0..self.voices)
+            .map(|_| rng.gen::<f32>() * self.phase_randomness)
+            .collect::<Vec<_>>();
+        let voices = offsets
+            .iter()
+            .map(|offset| self.inner.create_state_at(phase + offset))
+            .collect();
+        UnisonState {
+            voices,
+            offsets,
+            freqs: Vec::new(),
+            amps: Vec::new(),
+        }
+    }
+
+    fn reset_phase(&self, state: &mut Self::State, phase: f32) {
+        for (voice, offset) in state.voices.iter_mut().zip(&state.offsets) {
+            self.inner.reset_phase(voice, phase + offset);
+        }
+    }
+
+    fn fill_samples(
+        &self,
+        state: &mut Self::State,
+        buffer: &mut [f32],
+        delta_t: f32,
+        freq: f32,
+        amp: f32,
+    ) {
+        let voice_amp = amp * self.voice_amp();
+        for (ix, voice) in state.voices.iter_mut().enumerate() {
+            let voice_freq = freq * self.voice_ratio(ix);
+            self.inner
+                .fill_samples(voice, buffer, delta_t, voice_freq, voice_amp);
+        }
+    }
+
+    fn fill_samples_modulated(
+        &self,
+        state: &mut Self::State,
+        buffer: &mut [f32],
+        delta_t: f32,
+        freqs: &[f32],
+        amps: &[f32],
+    ) {
+        let len = buffer.len();
+        let voice_amp = self.voice_amp();
+        state.amps.clear();
+        state.amps.extend(amps[..len].iter().map(|a| a * voice_amp));
+        state.freqs.resize(len, 0.0);
+        for (ix, voice) in state.voices.iter_mut().enumerate() {
+            let ratio = self.voice_ratio(ix);
+            for (voice_freq, freq) in state.freqs.iter_mut().zip(freqs) {
+                *voice_freq = freq * ratio;
+            }
+            self.inner
+                .fill_samples_modulated(voice, buffer, delta_t, &state.freqs, &state.amps);
+        }
+    }
+}
+
+#[cfg(test)]
+fn render<O: Oscillator>(osc: &O, state: &mut O::State, freq: f32) -> Vec<f32> {
+    let mut buf = vec![0.0; 256];
+    osc.fill_samples(state, &mut buf, 1.0 / 44100.0, freq, 1.0);
+    buf
+}
+
+#[test]
+fn test_voices_spread_across_detune() {
+    use super::sine::SineOscillator;
+
+    // Two voices an octave apart sit half an octave either side of the note.
+    let unison = Unison::new(SineOscillator::default(), 2, 1200.0)
+        .with_phase_randomness(0.0)
+        .with_amplitude_compensation(false);
+    let sine = SineOscillator::default();
+    let expected = render(&sine, &mut sine.create_state(), 1000.0 / 2.0_f32.sqrt())
+        .iter()
+        .zip(render(
+            &sine,
+            &mut sine.create_state(),
+            1000.0 * 2.0_f32.sqrt(),
+        ))
+        .map(|(low, high)| low + high)
+        .collect::<Vec<_>>();
+    let actual = render(&unison, &mut unison.create_state(), 1000.0);
+    for (a, b) in actual.iter().zip(&expected) {
+        assert!((a - b).abs() < 1e-4, "{} != {}", a, b);
+    }
+}
+
+#[test]
+fn test_amplitude_compensation() {
+    use super::sine::SineOscillator;
+
+    // In phase and without detune, the voices add up to `voices` times a single sine, or to
+    // `sqrt(voices)` times with compensation.
+    for voices in [1, 2, 4, 9] {
+        for compensate in [false, true] {
+            let unison = Unison::new(SineOscillator::default(), voices, 0.0)
+                .with_phase_randomness(0.0)
+                .with_amplitude_compensation(compensate);
+            let peak = render(&unison, &mut unison.create_state(), 441.0)
+                .iter()
+                .fold(0.0, |peak: f32, x| peak.max(x.abs()));
+            let voices = voices as f32;
+            let expected = if compensate { voices.sqrt() } else { voices };
+            assert!((peak - expected).abs() < 1e-3, "{} != {}", peak, expected);
+        }
+    }
+}
+
+#[test]
+fn test_seeded_phase_offsets() {
+    use super::sine::SineOscillator;
+
+    let unison = Unison::new(SineOscillator::default(), 5, 20.0).with_seed(3);
+    let copy = unison.clone();
+    let mut state = unison.create_state();
+    let first = render(&unison, &mut state.clone(), 220.0);
+    assert_eq!(render(&copy, &mut copy.create_state(), 220.0), first);
+    assert_ne!(render(&unison, &mut unison.create_state(), 220.0), first);
+
+    // Resetting the phase brings back the same offsets.
+    render(&unison, &mut state, 220.0);
+    unison.reset_phase(&mut state, 0.0);
+    assert_eq!(render(&unison, &mut state, 220.0), first);
+}
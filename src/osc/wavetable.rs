@@ -0,0 +1,319 @@
+//! A wavetable oscillator playing back single-cycle frames.
+//!
+//! Every frame is stored as a set of band-limited copies ("mip levels"), each keeping half the
+//! harmonics of the previous one. During playback the level is chosen from the playback
+//! frequency so that no harmonic exceeds the Nyquist frequency.
+
+use super::Oscillator;
+
+/// The number of samples in every stored single-cycle table.
+pub const TABLE_SIZE: usize = 2048;
+
+/// The number of harmonics kept by mip level 0.
+const MAX_HARMONICS: usize = TABLE_SIZE / 2;
+
+#[derive(Debug, Clone)]
+struct Frame {
+    /// Band-limited copies of the frame. Level `i` keeps harmonics up to `MAX_HARMONICS >> i`.
+    mips: Vec<Vec<f32>>,
+}
+
+/// Fourier coefficients of a single cycle, `(cosine, sine)` for every harmonic. Index 0 is the
+/// DC offset.
+type Spectrum = Vec<(f32, f32)>;
+
+impl Frame {
+    fn from_spectrum(spectrum: &[(f32, f32)]) -> Self {
+        let cos_table = (0..TABLE_SIZE)
+            .map(|n| (2.0 * std::f64::consts::PI * n as f64 / TABLE_SIZE as f64).cos() as f32)
+            .collect::<Vec<_>>();
+        let sin_at = |i: usize| cos_table[(i + TABLE_SIZE * 3 / 4) % TABLE_SIZE];
+
+        let mut mips = Vec::new();
+        let mut max_harmonic = MAX_HARMONICS;
+        while max_harmonic >= 1 {
+            let mut table = vec![spectrum.first().map_or(0.0, |c| c.0); TABLE_SIZE];
+            let limit = max_harmonic.min(spectrum.len().saturating_sub(1));
+            for (k, (a, b)) in spectrum.iter().enumerate().take(limit + 1).skip(1) {
+                if *a == 0.0 && *b == 0.0 {
+                    continue;
+                }
+                for (n, sample) in table.iter_mut().enumerate() {
+                    let i = (k * n) % TABLE_SIZE;
+                    *sample += a * cos_table[i] + b * sin_at(i);
+                }
+            }
+            mips.push(table);
+            max_harmonic /= 2;
+        }
+        Frame { mips }
+    }
+
+    fn from_samples(samples: &[f32]) -> Self {
+        Self::from_spectrum(&analyze(samples))
+    }
+}
+
+/// Compute the Fourier series of one cycle of a waveform, up to the highest harmonic that both
+/// the input and the stored tables can represent.
+fn analyze(samples: &[f32]) -> Spectrum {
+    let len = samples.len();
+    if len == 0 {
+        return vec![];
+    }
+    let cos_table = (0..len)
+        .map(|n| (2.0 * std::f64::consts::PI * n as f64 / len as f64).cos())
+        .collect::<Vec<_>>();
+    let harmonics = ((len - 1) / 2).min(MAX_HARMONICS);
+
+    let dc = samples.iter().map(|x| *x as f64).sum::<f64>() / len as f64;
+    let mut spectrum = Vec::with_capacity(harmonics + 1);
+    spectrum.push((dc as f32, 0.0));
+    for k in 1..=harmonics {
+        let (mut a, mut b) = (0.0, 0.0);
+        for (n, x) in samples.iter().enumerate() {
+            let i = (k * n) % len;
+            a += *x as f64 * cos_table[i];
+            b += *x as f64 * cos_table[(i + len * 3 / 4) % len];
+        }
+        let scale = 2.0 / len as f64;
+        spectrum.push(((a * scale) as f32, (b * scale) as f32));
+    }
+    spectrum
+}
+
+/// An oscillator reading from a list of single-cycle frames.
+#[derive(Debug, Clone, Default)]
+pub struct WavetableOscillator {
+    frames: Vec<Frame>,
+    /// The morph position between the first (0) and the last (1) frame. Positions between two
+    /// frames are linearly interpolated.
+    pub position: f32,
+}
+
+impl WavetableOscillator {
+    /// Create a wavetable from raw single-cycle frames. Frames may be of any length; each one is
+    /// resampled into a table of [`TABLE_SIZE`] samples.
+    pub fn from_frames(frames: &[Vec<f32>]) -> Self {
+        Self {
+            frames: frames.iter().map(|f| Frame::from_samples(f)).collect(),
+            position: 0.0,
+        }
+    }
+
+    /// Create a single-frame wavetable from a list of sine harmonic amplitudes, interpreted the
+    /// same way as [`HarmonicOscillator::new`](super::harmonic::HarmonicOscillator::new).
+    pub fn from_harmonics(amps: &[f32]) -> Self {
+        let mut spectrum = vec![(0.0, 0.0)];
+        for (ix, amp) in amps.iter().enumerate() {
+            let multiplier = ix + 1;
+            spectrum.push((0.0, amp / multiplier as f32));
+        }
+        Self {
+            frames: vec![Frame::from_spectrum(&spectrum)],
+            position: 0.0,
+        }
+    }
+
+    /// Create a single-frame wavetable by rendering one cycle of another oscillator.
+    pub fn from_oscillator<O: Oscillator>(osc: &O) -> Self {
+        let mut state = osc.create_state();
+        let mut cycle = vec![0.0; TABLE_SIZE];
+        osc.fill_samples(&mut state, &mut cycle, 1.0 / TABLE_SIZE as f32, 1.0, 1.0);
+        Self::from_frames(&[cycle])
+    }
+
+    /// Set the morph position. See [`WavetableOscillator::position`].
+    pub fn with_position(mut self, position: f32) -> Self {
+        self.position = position;
+        self
+    }
+
+    /// The number of frames in this wavetable.
+    pub fn frame_count(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// Select the mip level whose highest harmonic stays below Nyquist at the given phase
+    /// increment per sample.
+    fn mip_level(&self, increment: f32) -> usize {
+        let levels = self.frames[0].mips.len();
+        let allowed = 0.5 / increment.abs();
+        (0..levels)
+            .find(|level| (MAX_HARMONICS >> level) as f32 <= allowed)
+            .unwrap_or(levels - 1)
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct WavetableOscillatorState {
+    /// A phase between 0 and 1.
+    phase: f32,
+}
+
+impl WavetableOscillatorState {
+    fn at(phase: f32) -> Self {
+        Self {
+            phase: phase.rem_euclid(1.0),
+        }
+    }
+}
+
+fn read_table(table: &[f32], phase: f32) -> f32 {
+    let idx = phase * TABLE_SIZE as f32;
+    let i0 = idx as usize % TABLE_SIZE;
+    let i1 = (i0 + 1) % TABLE_SIZE;
+    let frac = idx.fract();
+    table[i0] + (table[i1] - table[i0]) * frac
+}
+
+impl Oscillator for WavetableOscillator {
+    type State = WavetableOscillatorState;
+
+    fn create_state(&self) -> Self::State {
+        WavetableOscillatorState::default()
+    }
+
+    fn create_state_at(&self, phase: f32) -> Self::State {
+        WavetableOscillatorState::at(phase)
+    }
+
+    fn fill_samples(
+        &self,
+        state: &mut Self::State,
+        buffer: &mut [f32],
+        delta_t: f32,
+        freq: f32,
+        amp: f32,
+    ) {
+        if self.frames.is_empty() {
+            return;
+        }
+        let increment = freq * delta_t;
+        let level = self.mip_level(increment);
+
+        let position = self.position.clamp(0.0, 1.0) * (self.frames.len() - 1) as f32;
+        let first = position as usize;
+        let second = (first + 1).min(self.frames.len() - 1);
+        let mix = position - first as f32;
+        let first = &self.frames[first].mips[level];
+        let second = &self.frames[second].mips[level];
+
+        for sample in buffer.iter_mut() {
+            let a = read_table(first, state.phase);
+            let b = read_table(second, state.phase);
+            *sample += (a + (b - a) * mix) * amp;
+            state.phase += increment;
+            state.phase = state.phase.rem_euclid(1.0);
+        }
+    }
+}
+
+#[test]
+fn test_wavetable_from_oscillator() {
+    use super::sine::SineOscillator;
+
+    let sine = WavetableOscillator::from_oscillator(&SineOscillator::default());
+    let mut state = sine.create_state();
+    let mut buffer = vec![0.0; 64];
+    sine.fill_samples(&mut state, &mut buffer, 1.0 / 64.0, 1.0, 1.0);
+    for (n, sample) in buffer.iter().enumerate() {
+        let expected = (2.0 * std::f32::consts::PI * n as f32 / 64.0).sin();
+        assert!(
+            (sample - expected).abs() < 1e-3,
+            "{} != {}",
+            sample,
+            expected
+        );
+    }
+}
+
+#[cfg(test)]
+fn render(osc: &WavetableOscillator, len: usize, freq: f32) -> Vec<f32> {
+    let mut buf = vec![0.0; len];
+    osc.fill_samples(&mut osc.create_state(), &mut buf, 1.0 / 44100.0, freq, 1.0);
+    buf
+}
+
+/// The amplitude of the component at `freq` in `samples`, rendered at 44.1 kHz. Exact for whole
+/// numbers of cycles.
+#[cfg(test)]
+fn magnitude_at(samples: &[f32], freq: f32) -> f32 {
+    let w = std::f32::consts::TAU * freq / 44100.0;
+    let (re, im) = samples
+        .iter()
+        .enumerate()
+        .fold((0.0, 0.0), |(re, im), (n, x)| {
+            let (sin, cos) = (w * n as f32).sin_cos();
+            (re + x * cos, im + x * sin)
+        });
+    2.0 * f32::hypot(re, im) / samples.len() as f32
+}
+
+#[test]
+fn test_position_interpolates_between_frames() {
+    // Three frames holding the first, second and third harmonic. Position 0.75 lies halfway
+    // between the second and the third frame.
+    let harmonic = |k: usize| {
+        (0..64)
+            .map(|n| (std::f32::consts::TAU * (k * n) as f32 / 64.0).sin())
+            .collect::<Vec<_>>()
+    };
+    let wavetable = WavetableOscillator::from_frames(&[harmonic(1), harmonic(2), harmonic(3)]);
+    for (position, weights) in [
+        (0.0, [1.0, 0.0, 0.0]),
+        (0.25, [0.5, 0.5, 0.0]),
+        (0.75, [0.0, 0.5, 0.5]),
+        (1.0, [0.0, 0.0, 1.0]),
+    ] {
+        let out = render(&wavetable.clone().with_position(position), 256, 100.0);
+        for (n, sample) in out.iter().enumerate() {
+            let t = n as f32 / 44100.0;
+            let expected = weights
+                .iter()
+                .enumerate()
+                .map(|(k, w)| w * (std::f32::consts::TAU * 100.0 * (k + 1) as f32 * t).sin())
+                .sum::<f32>();
+            assert!(
+                (sample - expected).abs() < 1e-3,
+                "{} != {} at position {}",
+                sample,
+                expected,
+                position
+            );
+        }
+    }
+}
+
+#[test]
+fn test_mip_level_drops_harmonics_above_nyquist() {
+    let saw = WavetableOscillator::from_harmonics(&[1.0; 64]);
+    // At 100 Hz, all 64 harmonics fit below Nyquist.
+    let low = render(&saw, 4410, 100.0);
+    for k in [1, 6, 64] {
+        let expected = 1.0 / k as f32;
+        let magnitude = magnitude_at(&low, 100.0 * k as f32);
+        assert!(
+            (magnitude - expected).abs() < 1e-2,
+            "{} != {}",
+            magnitude,
+            expected
+        );
+    }
+    // At 5 kHz, only the first four harmonics do. The fifth and sixth would alias to 19.1 kHz and
+    // 14.1 kHz.
+    let high = render(&saw, 4410, 5000.0);
+    for k in 1..=4 {
+        let expected = 1.0 / k as f32;
+        let magnitude = magnitude_at(&high, 5000.0 * k as f32);
+        assert!(
+            (magnitude - expected).abs() < 1e-2,
+            "{} != {}",
+            magnitude,
+            expected
+        );
+    }
+    for alias in [19100.0, 14100.0] {
+        assert!(magnitude_at(&high, alias) < 1e-3);
+    }
+}